@@ -1,19 +1,40 @@
-use ag::pipeline::{ErrorReporter, Pipeline, QueryContainer};
+use ag::pipeline::{
+    Config, ErrorMode, ErrorReporter, Input, OutputFormat, Pipeline, QueryContainer,
+};
+use annotate_snippets::display_list::{DisplayLine, DisplayList, DisplayRawLine};
 use annotate_snippets::snippet::Snippet;
 use atty::Stream;
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
 use human_panic::setup_panic;
+use memmap::Mmap;
 use quicli::prelude::*;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
 use self_update;
 use self_update::cargo_crate_version;
 use std::env;
-use std::fs::File;
+use std::fs::{self, File, Metadata};
 use std::io;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Read};
+use std::iter;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
+use xz2::bufread::XzDecoder;
 
 use structopt::clap::ArgGroup;
 
-// Needed to require either "--self-update" or a query
+/// Size of the buffer used to read the input.  Whatever is read at once is handed to the worker
+/// threads as a single chunk, so a larger buffer means fewer, bigger chunks.
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Files modified more recently than this may still be written to, so they aren't mapped.
+const SETTLED_AFTER: Duration = Duration::from_secs(10);
+
+// Needed to require either "--self-update", "--repl" or a query, from the command line or a file
 fn main_arg_group() -> ArgGroup<'static> {
     ArgGroup::with_name("main").required(true)
 }
@@ -24,17 +45,60 @@ fn main_arg_group() -> ArgGroup<'static> {
     raw(group = "main_arg_group()")
 )]
 struct Cli {
-    /// The query
+    /// The query.  It can start with a saved query and use macros from the config files,
+    /// ~/.config/agrind/config.toml and .agrind.toml, e.g. `@errors | @nginx | count by status`
     #[structopt(group = "main")]
     query: Option<String>,
 
+    /// Read the query from a file instead.  The operators can be on separate lines, and `#`
+    /// followed by a space starts a comment that runs to the end of the line
+    #[structopt(long = "query-file", group = "main")]
+    query_file: Option<String>,
+
     /// Update agrind to the latest published version Github (https://github.com/rcoh/angle-grinder)
     #[structopt(long = "self-update", group = "main")]
     update: bool,
 
-    /// Optionally reads from a file instead of Stdin
-    #[structopt(long = "file", short = "f")]
-    file: Option<String>,
+    /// Optionally reads from files instead of Stdin. May be given more than once and accepts
+    /// globs. Each record gets a `_file` column with the name of its file, which is only output
+    /// if the query refers to it.
+    #[structopt(long = "file", short = "f", raw(number_of_values = "1"))]
+    file: Vec<String>,
+
+    /// The output format: table (the default), json, csv, tsv, logfmt, markdown or html
+    #[structopt(long = "output", short = "o")]
+    output: Option<OutputFormat>,
+
+    /// Show aggregates in a full-screen view that can be scrolled, sorted and paused
+    #[structopt(long = "interactive", short = "i")]
+    interactive: bool,
+
+    /// Stop at the first record that an operator fails on
+    #[structopt(long = "strict")]
+    strict: bool,
+
+    /// Only print a summary of the records that operators failed on, instead of each error
+    #[structopt(long = "quiet-errors")]
+    quiet_errors: bool,
+
+    /// Give a value to a `$name` parameter in the query, e.g. `--param host=web-1`. May be given
+    /// more than once
+    #[structopt(long = "param", parse(try_from_str = "parse_param"))]
+    params: Vec<(String, String)>,
+
+    /// Load the files into memory once and run queries typed at a prompt against them
+    #[structopt(long = "repl", group = "main")]
+    repl: bool,
+
+    /// Print the query in its canonical form instead of running it.  A query from a file is
+    /// printed with each operator on its own line
+    #[structopt(long = "fmt")]
+    fmt: bool,
+
+    /// Print the plan the query is compiled into instead of running it
+    #[structopt(long = "explain")]
+    explain: bool,
+
     #[structopt(flatten)]
     verbosity: Verbosity,
 }
@@ -43,6 +107,15 @@ struct Cli {
 pub enum InvalidArgs {
     #[fail(display = "Query was missing. Usage: `agrind 'query'`")]
     MissingQuery,
+
+    #[fail(display = "The REPL reads queries from stdin, so the input has to come from --file")]
+    ReplWithoutFiles,
+
+    #[fail(display = "Couldn't read the query from {}: {}", path, message)]
+    QueryFile { path: String, message: String },
+
+    #[fail(display = "None of the input files could be read")]
+    NoInputFiles,
 }
 
 /// An ErrorReporter that writes errors related to the query string to the terminal
@@ -52,7 +125,17 @@ struct TermErrorReporter {
 
 impl ErrorReporter for TermErrorReporter {
     fn handle_error(&self, snippet: Snippet) {
-        let dl = annotate_snippets::display_list::DisplayList::from(snippet);
+        let mut dl = DisplayList::from(snippet);
+        // annotate-snippets counts the column in `--> file:line:column` from 0.
+        for line in &mut dl.body {
+            if let DisplayLine::Raw(DisplayRawLine::Origin {
+                pos: Some((_, ref mut column)),
+                ..
+            }) = line
+            {
+                *column += 1;
+            }
+        }
 
         eprintln!("{}", self.formatter.format(&dl));
     }
@@ -64,30 +147,303 @@ fn main() -> CliResult {
     if args.update {
         return update();
     }
-    let query = QueryContainer::new(
-        args.query.ok_or(InvalidArgs::MissingQuery)?,
-        Box::new(TermErrorReporter {
-            formatter: annotate_snippets::formatter::DisplayListFormatter::new(
-                env::var("NO_COLOR").is_err() && atty::is(Stream::Stderr),
-            ),
-        }),
-    );
     args.verbosity.setup_env_logger("agrind")?;
-    let pipeline = Pipeline::new(&query)?;
-    match args.file {
-        Some(file_name) => {
-            let f = File::open(file_name)?;
-            pipeline.process(BufReader::new(f))
+    if args.repl {
+        return repl(&args);
+    }
+    let text = match args.query_file {
+        Some(ref path) => fs::read_to_string(path).map_err(|e| InvalidArgs::QueryFile {
+            path: path.clone(),
+            message: e.to_string(),
+        })?,
+        None => args.query.clone().ok_or(InvalidArgs::MissingQuery)?,
+    };
+    if args.fmt {
+        return format(&args, text);
+    }
+    let config = load_config(&text)?;
+    let mut query = QueryContainer::with_config(text, term_reporter(), &config);
+    if let Some(ref path) = args.query_file {
+        query = query.with_origin(path.clone());
+    }
+    let query = query.with_params(args.params.clone());
+    let pipeline = pipeline(&args, &query)?;
+    if args.explain {
+        print!("{}", pipeline.explain());
+        return Ok(());
+    }
+    let paths = expand_globs(&args.file);
+    if paths.is_empty() {
+        return Ok(pipeline.process(BufReader::with_capacity(READ_BUFFER_SIZE, io::stdin()))?);
+    }
+    // The files are opened as they're needed, but at least one has to open before anything is
+    // rendered.
+    let mut paths = paths.iter();
+    let first = paths
+        .by_ref()
+        .find_map(|path| open_file(path))
+        .ok_or(InvalidArgs::NoInputFiles)?;
+    Ok(
+        pipeline
+            .process_files(iter::once(first).chain(paths.filter_map(|path| open_file(path))))?,
+    )
+}
+
+/// Load the config files, but only if the query refers to a saved query or macro, so a broken
+/// config doesn't get in the way of queries that don't use it.
+fn load_config(query: &str) -> Result<Config, Error> {
+    if Config::has_references(query) {
+        Ok(Config::load()?)
+    } else {
+        Ok(Config::default())
+    }
+}
+
+/// Print the query in its canonical form.  Saved queries and macros aren't expanded, and
+/// parameters are left in place.
+fn format(args: &Cli, text: String) -> CliResult {
+    let mut query = QueryContainer::new(text, term_reporter());
+    if let Some(ref path) = args.query_file {
+        query = query.with_origin(path.clone());
+    }
+    let parsed = match query.parse_unbound() {
+        Ok(parsed) => parsed,
+        // The error has been reported against the query, so there's nothing more to print.
+        Err(_) => process::exit(1),
+    };
+    if args.query_file.is_some() {
+        println!("{:#}", parsed);
+    } else {
+        println!("{}", parsed);
+    }
+    Ok(())
+}
+
+fn term_reporter() -> Box<ErrorReporter> {
+    Box::new(TermErrorReporter {
+        formatter: annotate_snippets::formatter::DisplayListFormatter::new(
+            env::var("NO_COLOR").is_err() && atty::is(Stream::Stderr),
+        ),
+    })
+}
+
+/// Compile the query with the options given on the command line.
+fn pipeline(args: &Cli, query: &QueryContainer) -> Result<Pipeline, Error> {
+    Ok(
+        Pipeline::new(query, args.output.unwrap_or(OutputFormat::Table))?
+            .interactive(args.interactive)
+            .error_mode(if args.strict {
+                ErrorMode::Strict
+            } else if args.quiet_errors {
+                ErrorMode::Summary
+            } else {
+                ErrorMode::Print
+            }),
+    )
+}
+
+/// An input file loaded for the REPL.  Files that aren't compressed stay mapped into memory,
+/// everything else is decompressed once up front.
+type Loaded = Arc<AsRef<[u8]> + Send + Sync>;
+
+/// Where the REPL keeps the queries typed in earlier sessions.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|dir| dir.join("agrind").join("history"))
+}
+
+/// Load the files once, then run each query typed at the prompt against them until the input
+/// ends.  Errors in a query are reported and the prompt is shown again.
+fn repl(args: &Cli) -> CliResult {
+    let paths = expand_globs(&args.file);
+    if paths.is_empty() {
+        return Err(InvalidArgs::ReplWithoutFiles.into());
+    }
+    let mut inputs = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let loaded: Loaded = match open_input(path)? {
+            Input::Mapped(map) => Arc::new(map),
+            Input::Stream(mut reader) => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                Arc::new(data)
+            }
+            Input::Loaded(data) => data,
+        };
+        inputs.push((path.clone(), loaded));
+    }
+    eprintln!(
+        "Loaded {}. Type a query to run it, or Ctrl-D to quit.",
+        paths.join(", ")
+    );
+
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(ref path) = history {
+        // There's no history the first time.
+        let _ = editor.load_history(path);
+    }
+    loop {
+        let line = match editor.readline("agrind> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
         }
-        None => {
-            let stdin = io::stdin();
-            let locked = stdin.lock();
-            pipeline.process(locked)
+        editor.add_history_entry(line)?;
+        if let Err(e) = run_query(args, line, &inputs) {
+            eprintln!("error: {}", e);
         }
-    };
+    }
+    if let Some(ref path) = history {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = editor.save_history(path);
+    }
     Ok(())
 }
 
+/// Run a query typed into the REPL against the loaded files.
+fn run_query(args: &Cli, line: &str, inputs: &[(String, Loaded)]) -> Result<(), Error> {
+    let config = load_config(line)?;
+    let query = QueryContainer::with_config(line.to_string(), term_reporter(), &config)
+        .with_params(args.params.clone());
+    let pipeline = match pipeline(args, &query) {
+        Ok(pipeline) => pipeline,
+        // The errors have already been shown against the query.
+        Err(_) if !query.errors().is_empty() => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if args.explain {
+        print!("{}", pipeline.explain());
+        return Ok(());
+    }
+    // The loaded files are split up like mapped files, so the worker threads share the work.
+    pipeline.process_files(
+        inputs
+            .iter()
+            .map(|(name, data)| (name.clone(), Input::<&[u8]>::Loaded(data.clone()))),
+    )
+}
+
+/// Split a `--param` argument into the name and value.
+fn parse_param(param: &str) -> Result<(String, String), String> {
+    match param.find('=') {
+        Some(pos) if pos > 0 => Ok((param[..pos].to_string(), param[pos + 1..].to_string())),
+        _ => Err(format!("expected name=value, found `{}`", param)),
+    }
+}
+
+/// Expand any globs in the file arguments.  Arguments that don't match anything are passed
+/// through as-is so that opening them reports a useful error.
+fn expand_globs(patterns: &[String]) -> Vec<String> {
+    patterns
+        .iter()
+        .flat_map(|pattern| {
+            let matches: Vec<String> = glob::glob(pattern)
+                .map(|paths| {
+                    paths
+                        .filter_map(Result::ok)
+                        .map(|path| path.to_string_lossy().into_owned())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if matches.is_empty() {
+                vec![pattern.clone()]
+            } else {
+                matches
+            }
+        })
+        .collect()
+}
+
+/// Compression formats that are transparently decompressed when reading from a file.
+#[derive(Debug, PartialEq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Detect the compression format from the magic bytes at the start of the input.
+    fn detect(header: &[u8]) -> Option<Compression> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if header.len() > 3
+            && header.starts_with(b"BZh")
+            && (b'1'..=b'9').contains(&header[3])
+        {
+            // The magic is followed by the block size, 1-9.
+            Some(Compression::Bzip2)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Open a file for reading, wrapping it in a decoder if it is compressed.  Regular files that
+/// aren't compressed or still being written are memory-mapped so they can be split up without
+/// reading them first.
+fn open_input(path: &str) -> io::Result<Input<Box<BufRead>>> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, file);
+    let compression = Compression::detect(reader.fill_buf()?);
+    let reader: Box<BufRead> = match compression {
+        Some(Compression::Gzip) => Box::new(decoded(MultiGzDecoder::new(reader))),
+        Some(Compression::Zstd) => Box::new(decoded(zstd::Decoder::with_buffer(reader)?)),
+        Some(Compression::Bzip2) => Box::new(decoded(MultiBzDecoder::new(reader))),
+        Some(Compression::Xz) => Box::new(decoded(XzDecoder::new_multi_decoder(reader))),
+        None if can_map(&metadata) => {
+            // Safe as long as the file isn't truncated while it's being read.
+            let map = unsafe { Mmap::map(reader.get_ref())? };
+            return Ok(Input::Mapped(map));
+        }
+        None => Box::new(reader),
+    };
+    Ok(Input::Stream(reader))
+}
+
+/// Mapping an empty file fails, and pipes and devices can't be mapped at all.  A file that is
+/// being written to, like a log that is being followed, is read like a stream instead: it could
+/// be truncated while it's mapped, and anything added to it wouldn't be seen.
+fn can_map(metadata: &Metadata) -> bool {
+    let settled = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > SETTLED_AFTER);
+    metadata.file_type().is_file() && metadata.len() > 0 && settled
+}
+
+fn decoded<R: Read>(decoder: R) -> BufReader<R> {
+    BufReader::with_capacity(READ_BUFFER_SIZE, decoder)
+}
+
+/// Open one of several input files, reporting the error and skipping the file if it can't be read.
+fn open_file(path: &str) -> Option<(String, Input<Box<BufRead>>)> {
+    match open_input(path) {
+        Ok(input) => Some((path.to_string(), input)),
+        Err(e) => {
+            eprintln!("error: {}: {}", path, e);
+            None
+        }
+    }
+}
+
 fn update() -> CliResult {
     let target = self_update::get_target()?;
     let status = self_update::backends::github::Update::configure()?
@@ -0,0 +1,357 @@
+//! Saved queries and operator macros from the config files.  A saved query is run by starting a
+//! query with its name, e.g. `agrind '@errors | count by status'`, and a macro stands in for one
+//! or more operators, e.g. `* | @nginx | count by status`.  References are expanded into the
+//! query text before it's parsed, and the expansions are kept so errors can be reported against
+//! the definitions.
+use crate::errors::did_you_mean;
+use crate::lang::{comment_len, space_and_comments_len};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// The name of the config file that applies to a directory and everything under it.
+pub const PROJECT_CONFIG: &str = ".agrind.toml";
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum ConfigError {
+    #[fail(display = "Couldn't read {}: {}", path, message)]
+    Read { path: String, message: String },
+
+    #[fail(display = "Invalid config file {}: {}", path, message)]
+    Invalid { path: String, message: String },
+}
+
+/// A saved query or macro, with the file it's defined in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub text: String,
+    pub origin: String,
+}
+
+/// The saved queries and macros from the config files.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    queries: HashMap<String, Definition>,
+    macros: HashMap<String, Definition>,
+}
+
+/// A reference in a query that was replaced by its definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    pub origin: String,
+    /// The definition that replaced the reference.
+    pub text: String,
+    /// Where the reference is in the query as it was written.
+    pub reference: Range<usize>,
+    /// Where the definition is in the expanded query.
+    pub expanded: Range<usize>,
+}
+
+/// A reference to a macro that isn't defined.
+#[derive(Debug, PartialEq)]
+pub struct UnknownMacro {
+    pub name: String,
+    pub range: Range<usize>,
+    pub suggestion: Option<String>,
+}
+
+fn is_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+impl Config {
+    /// Load the user's config, `~/.config/agrind/config.toml`, followed by the first
+    /// `.agrind.toml` in the current directory or one of its parents.  Definitions in the
+    /// project's config replace the user's.  Missing files are skipped.
+    pub fn load() -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        let user_config = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("agrind").join("config.toml"));
+        if let Some(path) = user_config {
+            config.load_file(&path)?;
+        }
+        let project_config = env::current_dir().ok().and_then(|dir| {
+            dir.ancestors()
+                .map(|dir| dir.join(PROJECT_CONFIG))
+                .find(|path| path.is_file())
+        });
+        if let Some(path) = project_config {
+            config.load_file(&path)?;
+        }
+        Ok(config)
+    }
+
+    fn load_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let origin = path.display().to_string();
+        match fs::read_to_string(path) {
+            Ok(text) => self.add_toml(&text, &origin),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(ConfigError::Read {
+                path: origin,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    /// Add the definitions in a config file, replacing any with the same name.  `origin` names
+    /// the file in error messages.
+    pub fn add_toml(&mut self, text: &str, origin: &str) -> Result<(), ConfigError> {
+        let invalid = |message: String| ConfigError::Invalid {
+            path: origin.to_string(),
+            message,
+        };
+        let value = text
+            .parse::<toml::Value>()
+            .map_err(|e| invalid(e.to_string()))?;
+        let tables = value
+            .as_table()
+            .ok_or_else(|| invalid("expected a table".to_string()))?;
+        for (section, table) in tables {
+            let definitions = match section.as_str() {
+                "queries" => &mut self.queries,
+                "macros" => &mut self.macros,
+                _ => return Err(invalid(format!("unknown section `{}`", section))),
+            };
+            let table = table
+                .as_table()
+                .ok_or_else(|| invalid(format!("`{}` should be a table", section)))?;
+            for (name, text) in table {
+                if name.is_empty() || !name.chars().all(is_name) {
+                    return Err(invalid(format!(
+                        "`{}` can only contain letters, digits, `_` and `-`",
+                        name
+                    )));
+                }
+                let text = text
+                    .as_str()
+                    .ok_or_else(|| invalid(format!("`{}.{}` should be a string", section, name)))?;
+                definitions.insert(
+                    name.clone(),
+                    Definition {
+                        text: text.trim().to_string(),
+                        origin: origin.to_string(),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the query refers to a saved query or macro, so the config files are needed to run
+    /// it.  An `@` in a string or comment doesn't count.
+    pub fn has_references(query: &str) -> bool {
+        !references(query).is_empty()
+    }
+
+    /// Replace the references in the query with their definitions.  A query can start with a
+    /// saved query, and macros can be used wherever an operator can.  Definitions aren't
+    /// expanded themselves, so they can't refer to other definitions.  References in comments
+    /// are left alone.
+    pub fn expand(&self, query: &str) -> Result<(String, Vec<Expansion>), UnknownMacro> {
+        let mut found = Vec::new();
+        for (range, name, is_start) in references(query) {
+            if is_start {
+                // A query that starts with an `@` is a keyword search unless it names a saved
+                // query.
+                if let Some(definition) = self.queries.get(name) {
+                    found.push((range, name, definition));
+                }
+                continue;
+            }
+            match self.macros.get(name) {
+                Some(definition) => found.push((range, name, definition)),
+                None => {
+                    let names: Vec<&str> = self.macros.keys().map(String::as_str).collect();
+                    return Err(UnknownMacro {
+                        name: name.to_string(),
+                        range,
+                        suggestion: did_you_mean(name, &names),
+                    });
+                }
+            }
+        }
+
+        let mut expanded = String::new();
+        let mut expansions = Vec::new();
+        let mut last = 0;
+        for (range, name, definition) in found {
+            expanded.push_str(&query[last..range.start]);
+            let start = expanded.len();
+            expanded.push_str(&definition.text);
+            expansions.push(Expansion {
+                name: name.to_string(),
+                origin: definition.origin.clone(),
+                text: definition.text.clone(),
+                expanded: start..expanded.len(),
+                reference: range.clone(),
+            });
+            last = range.end;
+        }
+        expanded.push_str(&query[last..]);
+        Ok((expanded, expansions))
+    }
+}
+
+/// The `@` references in the query, with whether each is at the start of the query, where it can
+/// name a saved query, rather than in place of an operator.  Strings and comments are skipped.
+fn references(query: &str) -> Vec<(Range<usize>, &str, bool)> {
+    let mut references = Vec::new();
+    let start = space_and_comments_len(query);
+    if let Some(name) = reference_at(query, start) {
+        references.push((start..start + name.len() + 1, name, true));
+    }
+
+    let mut quote = None;
+    let mut escaped = false;
+    let mut comment_end = 0;
+    for (i, c) in query.char_indices() {
+        let after_space = query[..i].ends_with(char::is_whitespace) || i == 0;
+        match quote {
+            _ if i < comment_end => (),
+            _ if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if after_space && comment_len(&query[i..]).is_some() => {
+                comment_end = i + comment_len(&query[i..]).unwrap();
+            }
+            None if c == '|' => {
+                let pos = i + 1 + space_and_comments_len(&query[i + 1..]);
+                if let Some(name) = reference_at(query, pos) {
+                    references.push((pos..pos + name.len() + 1, name, false));
+                }
+            }
+            None => (),
+        }
+    }
+    references
+}
+
+/// The name referenced by an `@` at the given position, if there is one.
+fn reference_at(query: &str, pos: usize) -> Option<&str> {
+    let rest = query[pos..].strip_prefix('@')?;
+    let end = rest.find(|c| !is_name(c)).unwrap_or(rest.len());
+    let next = rest[end..].chars().next();
+    if end == 0 || next.is_some_and(|c| !c.is_whitespace() && c != '|') {
+        return None;
+    }
+    Some(&rest[..end])
+}
+
+/// Map a position in the expanded query back to the query as it was written.  Positions inside
+/// an expansion map to the reference.
+pub fn original_position(expansions: &[Expansion], pos: usize, end: bool) -> usize {
+    let mut shift = 0isize;
+    for expansion in expansions {
+        if pos < expansion.expanded.start || (end && pos == expansion.expanded.start) {
+            break;
+        }
+        if pos < expansion.expanded.end || (end && pos == expansion.expanded.end) {
+            return if end {
+                expansion.reference.end
+            } else {
+                expansion.reference.start
+            };
+        }
+        shift += expansion.expanded.len() as isize - expansion.reference.len() as isize;
+    }
+    (pos as isize - shift) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config
+            .add_toml(
+                r#"
+                [queries]
+                errors = '* | json | where level == "error"'
+
+                [macros]
+                nginx = 'parse "* * *" as ip, req, status'
+                "#,
+                "config.toml",
+            )
+            .unwrap();
+        config
+    }
+
+    #[test]
+    fn expand() {
+        let query = "@errors | @nginx | count by status";
+        let (expanded, expansions) = config().expand(query).unwrap();
+        assert_eq!(
+            expanded,
+            r#"* | json | where level == "error" | parse "* * *" as ip, req, status | count by status"#
+        );
+        assert_eq!(expansions.len(), 2);
+        assert_eq!(&query[expansions[1].reference.clone()], "@nginx");
+        assert_eq!(
+            &expanded[expansions[1].expanded.clone()],
+            r#"parse "* * *" as ip, req, status"#
+        );
+
+        // Positions after an expansion are shifted back, positions inside it map to the
+        // reference.
+        let count = expanded.find("count").unwrap();
+        assert_eq!(
+            original_position(&expansions, count, false),
+            query.find("count").unwrap()
+        );
+        let ip = expanded.find("ip").unwrap();
+        assert_eq!(original_position(&expansions, ip, false), 10);
+        assert_eq!(original_position(&expansions, ip, true), 16);
+    }
+
+    #[test]
+    fn expand_leaves_keywords_and_strings() {
+        let config = config();
+        for query in &[
+            "@unknown | count",
+            "user@example.com | count",
+            r#"* | parse "| @nginx" as x"#,
+            r#"* | where x == '\' | @nginx'"#,
+            "* | json # count | @nginx\n| count",
+        ] {
+            assert_eq!(config.expand(query).unwrap(), (query.to_string(), vec![]));
+        }
+        assert!(Config::has_references("@unknown | count"));
+        assert!(Config::has_references("* | @nginx"));
+        assert!(!Config::has_references(r#""user@example.com" | count"#));
+        assert!(!Config::has_references(r#"* | parse "| @nginx" as x"#));
+        assert_eq!(
+            config.expand("* | @ngin | count").unwrap_err(),
+            UnknownMacro {
+                name: "ngin".to_string(),
+                range: 4..9,
+                suggestion: Some("nginx".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_config() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.add_toml("[macros]\nfoo = 5", "a.toml"),
+            Err(ConfigError::Invalid {
+                path: "a.toml".to_string(),
+                message: "`macros.foo` should be a string".to_string()
+            })
+        );
+        assert!(config.add_toml("[alias]\nfoo = 'json'", "a.toml").is_err());
+        assert!(config
+            .add_toml("[macros]\n'a b' = 'json'", "a.toml")
+            .is_err());
+    }
+}
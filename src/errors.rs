@@ -1,18 +1,42 @@
-use crate::lang::{
-    query, Positioned, Query, QueryPosition, Span, VALID_AGGREGATES, VALID_INLINE, VALID_OPERATORS,
-};
+use crate::config::{self, Config, Expansion};
+use crate::lang::{query, trim_end_comments, Positioned, Query, QueryPosition, Span, VALID_INLINE};
+use crate::params;
+use crate::registry;
 use annotate_snippets::snippet::{Annotation, AnnotationType, Slice, Snippet, SourceAnnotation};
 use nom::types::CompleteStr;
 use nom::ErrorKind;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use std::cell::RefCell;
 use std::convert::From;
+use std::ops::Range;
 use strsim::normalized_levenshtein;
 
 /// Container for the query string that can be used to parse and report errors.
 pub struct QueryContainer {
+    /// The query with any saved queries and macros expanded.
     query: String,
+    /// The query as it was written.
+    original: String,
+    expansions: Vec<Expansion>,
+    /// Set to the position of a reference to an undefined macro, which fails parsing.
+    invalid: Option<QueryPosition>,
+    /// The values of the `$name` parameters in the query.
+    params: Vec<(String, String)>,
+    /// The file the query was read from, if any.
+    origin: Option<String>,
     reporter: Box<ErrorReporter>,
+    errors: RefCell<Vec<QueryError>>,
+}
+
+/// An error in the query, as passed to the `ErrorReporter`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    /// Byte ranges of the query that the error relates to, each with a label.
+    pub annotations: Vec<(Range<usize>, String)>,
+    /// Suggestions for fixing the error.
+    pub resolutions: Vec<String>,
 }
 
 /// Common syntax errors.
@@ -42,11 +66,100 @@ pub trait ErrorBuilder {
 
 impl QueryContainer {
     pub fn new(query: String, reporter: Box<ErrorReporter>) -> QueryContainer {
-        QueryContainer { query, reporter }
+        QueryContainer {
+            original: query.clone(),
+            query,
+            expansions: Vec::new(),
+            invalid: None,
+            params: Vec::new(),
+            origin: None,
+            reporter,
+            errors: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Create a container for a query that can use the saved queries and macros in the config.
+    /// References are expanded right away, and errors in the expanded query are reported
+    /// against the definitions they come from.  A reference to an undefined macro is reported
+    /// here and makes `parse` fail.
+    pub fn with_config(
+        query: String,
+        reporter: Box<ErrorReporter>,
+        config: &Config,
+    ) -> QueryContainer {
+        match config.expand(&query) {
+            Ok((expanded, expansions)) => QueryContainer {
+                query: expanded,
+                expansions,
+                ..QueryContainer::new(query, reporter)
+            },
+            Err(unknown) => {
+                let mut container = QueryContainer::new(query, reporter);
+                let mut report = container
+                    .report_error_for(format!("No macro named `{}`", unknown.name))
+                    .with_code_range(
+                        QueryPosition(unknown.range.start),
+                        QueryPosition(unknown.range.end),
+                        "",
+                    );
+                report = match unknown.suggestion {
+                    Some(suggestion) => {
+                        report.with_resolution(format!("Did you mean `@{}`?", suggestion))
+                    }
+                    None => report.with_resolution(
+                        "Macros are defined in ~/.config/agrind/config.toml or .agrind.toml",
+                    ),
+                };
+                report.send_report();
+                container.invalid = Some(QueryPosition(unknown.range.start));
+                container
+            }
+        }
+    }
+
+    /// The query string, with any saved queries and macros expanded.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Give values to the `$name` parameters in the query.  If a name is given more than once,
+    /// the last value is used.
+    pub fn with_params(mut self, params: Vec<(String, String)>) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Name the file the query was read from, so errors are reported with the line and column
+    /// in the file.
+    pub fn with_origin(mut self, origin: String) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Map a range of the expanded query back to the query as it was written.
+    fn original_range(&self, start: usize, end: usize) -> Range<usize> {
+        config::original_position(&self.expansions, start, false)
+            ..config::original_position(&self.expansions, end, true)
+    }
+
+    /// The errors that have been reported so far.
+    pub fn errors(&self) -> Vec<QueryError> {
+        self.errors.borrow().clone()
     }
 
     /// Parse the contained query string.
     pub fn parse(&self) -> Result<Query, QueryPosition> {
+        let mut query = self.parse_unbound()?;
+        params::bind(&mut query, &self.params, self)?;
+        Ok(query)
+    }
+
+    /// Parse the contained query string without binding the parameters, so `$name`
+    /// placeholders are left in the syntax tree.
+    pub fn parse_unbound(&self) -> Result<Query, QueryPosition> {
+        if let Some(ref pos) = self.invalid {
+            return Err(pos.clone());
+        }
         let parse_result = query(Span::new(CompleteStr(&self.query)));
 
         match parse_result {
@@ -98,14 +211,15 @@ impl ErrorBuilder for QueryContainer {
             query: self,
             data: SnippetData {
                 error: error.to_string(),
-                source: self.query.to_string(),
+                source: self.original.to_string(),
                 ..Default::default()
             },
         }
     }
 }
 
-fn did_you_mean(input: &str, choices: &[&str]) -> Option<String> {
+/// Find a choice that is similar enough to the input to be a likely typo.
+pub(crate) fn did_you_mean(input: &str, choices: &[&str]) -> Option<String> {
     let similarities = choices
         .iter()
         .map(|choice| (choice, normalized_levenshtein(choice, input)));
@@ -121,9 +235,16 @@ impl SyntaxErrors {
     pub fn to_resolution(&self, code_fragment: &str) -> Vec<String> {
         match self {
             SyntaxErrors::StartOfError => Vec::new(),
+            // Nothing that looks like a name was found, e.g. `* | 5`
+            SyntaxErrors::NotAnOperator if code_fragment.is_empty() => {
+                vec!["An operator starts with its name, like `where` or `count`".to_string()]
+            }
+            SyntaxErrors::NotAnAggregateOperator if code_fragment.is_empty() => vec![
+                "An aggregate operator starts with its name, like `count` or `sum`".to_string(),
+            ],
             SyntaxErrors::NotAnOperator => {
                 let mut res = vec![format!("{} is not a valid operator", code_fragment)];
-                if let Some(choice) = did_you_mean(code_fragment, &VALID_OPERATORS) {
+                if let Some(choice) = did_you_mean(code_fragment, &registry::valid_operators()) {
                     res.push(format!("Did you mean \"{}\"?", choice));
                 }
                 res
@@ -133,10 +254,12 @@ impl SyntaxErrors {
                     "{} is not a valid aggregate operator",
                     code_fragment
                 )];
-                if let Some(choice) = did_you_mean(code_fragment, &VALID_AGGREGATES) {
+                if let Some(choice) = did_you_mean(code_fragment, &registry::valid_aggregates()) {
                     res.push(format!("Did you mean \"{}\"?", choice));
                 }
-                if VALID_INLINE.contains(&code_fragment) {
+                if VALID_INLINE.contains(&code_fragment)
+                    || registry::inline_names().contains(&code_fragment)
+                {
                     res.push(format!("{} is an inline operator, but only aggregate operators (count, average, etc.) are valid here", code_fragment))
                 }
                 res
@@ -174,6 +297,28 @@ pub trait ErrorReporter {
     fn handle_error(&self, _snippet: Snippet) {}
 }
 
+/// Convert a range of the source to the offsets annotate-snippets expects.  Comments and line
+/// breaks after the annotated part are left out, so the annotation doesn't run on to the next
+/// line.
+fn trim_range(source: &str, start: usize, end: usize) -> (usize, usize) {
+    let text = &source[start..end];
+    let trimmed = trim_end_comments(text);
+    let rest = &text[trimmed.len()..];
+    let end = if trimmed.is_empty() || (rest.trim().is_empty() && !rest.contains('\n')) {
+        end
+    } else {
+        start + trimmed.len()
+    };
+    (display_offset(source, start), display_offset(source, end))
+}
+
+/// annotate-snippets counts characters rather than bytes, and takes each line to be followed
+/// by two characters, as if every line ended in `\r\n`.
+fn display_offset(source: &str, pos: usize) -> usize {
+    let before = &source[..pos];
+    before.chars().count() + before.matches('\n').count() - before.matches("\r\n").count()
+}
+
 /// Container for data that will be used to construct a Snippet
 #[derive(Default)]
 pub struct SnippetData {
@@ -226,29 +371,94 @@ impl<'a> SnippetBuilder<'a> {
     }
 
     /// Build and send the Snippet to the ErrorReporter in the QueryContainer.
-    pub fn send_report(mut self) {
+    pub fn send_report(self) {
+        self.query.errors.borrow_mut().push(QueryError {
+            message: self.data.error.clone(),
+            annotations: self
+                .data
+                .annotations
+                .iter()
+                .map(|&((start, end), ref label)| {
+                    (self.query.original_range(start, end), label.clone())
+                })
+                .collect(),
+            resolutions: self.data.resolution.clone(),
+        });
+        self.send(AnnotationType::Error);
+    }
+
+    /// Send the Snippet to the ErrorReporter as a warning.  Unlike errors, warnings aren't
+    /// returned by `QueryContainer::errors`.
+    pub fn send_warning(self) {
+        self.send(AnnotationType::Warning);
+    }
+
+    /// Annotations inside an expanded saved query or macro are shown on its definition, with
+    /// the reference to it highlighted in the query.
+    fn send(self, annotation_type: AnnotationType) {
+        let expansions = &self.query.expansions;
+        let mut annotations = Vec::new();
+        let mut expanded: Vec<(&Expansion, Vec<SourceAnnotation>)> = Vec::new();
+        for ((start, end), label) in self.data.annotations {
+            // Ranges can include the whitespace after the definition.
+            let expansion = expansions
+                .iter()
+                .find(|e| e.expanded.start <= start && start < e.expanded.end);
+            match expansion {
+                Some(expansion) => {
+                    let offset = expansion.expanded.start;
+                    let annotation = SourceAnnotation {
+                        range: trim_range(
+                            &expansion.text,
+                            start - offset,
+                            end.min(expansion.expanded.end) - offset,
+                        ),
+                        label,
+                        annotation_type,
+                    };
+                    match expanded.iter_mut().find(|(e, _)| *e == expansion) {
+                        Some((_, annotations)) => annotations.push(annotation),
+                        None => expanded.push((expansion, vec![annotation])),
+                    }
+                }
+                None => {
+                    let range = self.query.original_range(start, end);
+                    annotations.push(SourceAnnotation {
+                        range: trim_range(&self.data.source, range.start, range.end),
+                        label,
+                        annotation_type,
+                    });
+                }
+            }
+        }
+        for (expansion, _) in &expanded {
+            annotations.push(SourceAnnotation {
+                range: (expansion.reference.start, expansion.reference.end),
+                label: format!("in `@{}`", expansion.name),
+                annotation_type: AnnotationType::Info,
+            });
+        }
+        let mut slices = vec![Slice {
+            source: self.data.source,
+            line_start: 1,
+            origin: self.query.origin.clone(),
+            fold: false,
+            annotations,
+        }];
+        slices.extend(expanded.into_iter().map(|(expansion, annotations)| Slice {
+            source: expansion.text.clone(),
+            line_start: 1,
+            origin: Some(format!("@{} in {}", expansion.name, expansion.origin)),
+            fold: false,
+            annotations,
+        }));
         self.query.reporter.handle_error(Snippet {
             title: Some(Annotation {
                 label: Some(self.data.error),
                 id: None,
-                annotation_type: AnnotationType::Error,
+                annotation_type,
             }),
-            slices: vec![Slice {
-                source: self.data.source,
-                line_start: 1,
-                origin: None,
-                fold: false,
-                annotations: self
-                    .data
-                    .annotations
-                    .drain(..)
-                    .map(move |anno| SourceAnnotation {
-                        range: anno.0,
-                        label: anno.1,
-                        annotation_type: AnnotationType::Error,
-                    })
-                    .collect(),
-            }],
+            slices,
             footer: self
                 .data
                 .resolution
@@ -280,6 +490,10 @@ mod tests {
             SyntaxErrors::NotAnOperator.to_resolution("cont"),
             vec!["cont is not a valid operator", "Did you mean \"count\"?"]
         );
+        assert_eq!(
+            SyntaxErrors::NotAnOperator.to_resolution(""),
+            vec!["An operator starts with its name, like `where` or `count`"]
+        );
         assert_eq!(
             SyntaxErrors::NotAnAggregateOperator.to_resolution("parse"),
             vec![
@@ -0,0 +1,69 @@
+//! Describe the plan a query is compiled into, including the operators `Pipeline::new` adds or
+//! changes on its own, so it's possible to tell why a query behaves the way it does.
+use crate::lang::{trim_end_comments, Keyword, Positioned, SortMode, SortOperator};
+use crate::schema::Columns;
+use std::fmt::Write;
+
+/// An operator in the compiled pipeline.
+pub struct Step {
+    pub description: String,
+    /// Anything that isn't apparent from the query, like the operator being implicit.
+    pub note: Option<String>,
+    pub columns: Columns,
+}
+
+/// The text of an operator in the query.
+pub fn text<T>(query: &str, positioned: &Positioned<T>) -> String {
+    trim_end_comments(query[positioned.start_pos.0..positioned.end_pos.0].trim_start()).to_string()
+}
+
+pub fn sort(op: &SortOperator) -> String {
+    let direction = match op.direction {
+        SortMode::Ascending => "asc",
+        SortMode::Descending => "desc",
+    };
+    if op.sort_cols.is_empty() {
+        format!("sort {}", direction)
+    } else {
+        format!("sort by {} {}", op.sort_cols.join(", "), direction)
+    }
+}
+
+/// Render the regexes the search keywords compile to, followed by each section of operators.
+/// Empty sections are left out.
+pub fn render(keywords: &[Keyword], sections: &[(String, &[Step])]) -> String {
+    let mut out = String::new();
+    writeln!(out, "Filters:").unwrap();
+    if keywords.is_empty() {
+        writeln!(out, "  none, every line is processed").unwrap();
+    }
+    for keyword in keywords {
+        let filter = keyword.to_bytes_regex();
+        writeln!(out, "  {}  (from {})", filter.as_str(), keyword).unwrap();
+    }
+    for (title, steps) in sections.iter().filter(|(_, steps)| !steps.is_empty()) {
+        writeln!(out, "{}:", title).unwrap();
+        for step in *steps {
+            match step.note {
+                Some(ref note) => writeln!(out, "  {}  ({})", step.description, note),
+                None => writeln!(out, "  {}", step.description),
+            }
+            .unwrap();
+            if !step.columns.inputs.is_empty() {
+                writeln!(out, "    in: {}", step.columns.inputs.join(", ")).unwrap();
+            }
+            writeln!(out, "    out: {}", outputs(&step.columns)).unwrap();
+        }
+    }
+    out
+}
+
+fn outputs(columns: &Columns) -> String {
+    let outputs = columns.outputs.join(", ");
+    match (outputs.is_empty(), columns.open) {
+        (true, true) => "any fields from the input".to_string(),
+        (true, false) => "none".to_string(),
+        (false, true) => format!("{} and any fields from the input", outputs),
+        (false, false) => outputs,
+    }
+}
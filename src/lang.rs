@@ -1,5 +1,6 @@
 use crate::data;
 use crate::errors::SyntaxErrors;
+use crate::registry;
 use lazy_static::lazy_static;
 use nom;
 use nom::types::CompleteStr;
@@ -7,6 +8,7 @@ use nom::*;
 use nom::{digit1, double, is_alphabetic, is_alphanumeric, is_digit, multispace};
 use nom_locate::LocatedSpan;
 use std::convert::From;
+use std::fmt;
 use std::str;
 
 /// Wraps the result of the child parser in a Positioned and sets the start_pos and end_pos
@@ -32,11 +34,83 @@ macro_rules! with_pos {
       }
   });
   ($i:expr, $f:expr) => (
-    with_pos!($i, call!($f));
+    with_pos!($i, call!($f))
   );
 }
 
-/// Dynamic version of `alt` that takes a slice of strings
+/// Same as nom's `ws!()`, except that comments are skipped along with the whitespace, so a query
+/// can be spread over several lines with comments in between.  It has its own name so that it
+/// isn't mistaken for nom's.
+macro_rules! wsc (
+  ($i:expr, $($args:tt)*) => ({
+      use nom::Convert;
+      match sep!($i, space_or_comment, $($args)*) {
+          Err(e) => Err(e),
+          Ok((i1, o)) => match space_or_comment(i1) {
+              Err(e) => Err(nom::Err::convert(e)),
+              Ok((i2, _)) => Ok((i2, o)),
+          },
+      }
+  })
+);
+
+/// The length of the comment at the start of the text, if there is one.  A comment is a `#`
+/// followed by whitespace and runs to the end of the line.  Any other `#` is part of a keyword,
+/// so searching for `#channel` still works.
+pub(crate) fn comment_len(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('#')?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(text.find('\n').unwrap_or(text.len()))
+    } else {
+        None
+    }
+}
+
+/// The length of the whitespace and comments at the start of the text.
+pub(crate) fn space_and_comments_len(text: &str) -> usize {
+    let mut len = 0;
+    loop {
+        let rest = &text[len..];
+        len += rest.len() - rest.trim_start().len();
+        match comment_len(&text[len..]) {
+            Some(comment) => len += comment,
+            None => return len,
+        }
+    }
+}
+
+/// Remove the whitespace and comments from the end of a piece of the query.
+pub(crate) fn trim_end_comments(text: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match quote {
+            _ if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if space_and_comments_len(&text[i..]) == text.len() - i => return &text[..i],
+            None => (),
+        }
+    }
+    text
+}
+
+fn comment(input: Span) -> IResult<Span, Span> {
+    match comment_len(input.fragment.0) {
+        Some(len) => Ok(input.take_split(len)),
+        None => Err(nom::Err::Error(error_position!(input, ErrorKind::Tag))),
+    }
+}
+
+fn space_or_comment(input: Span) -> IResult<Span, Span> {
+    let len = space_and_comments_len(input.fragment.0);
+    Ok(input.take_split(len))
+}
+
+/// Dynamic version of `alt` that takes a slice of strings.  The longest match wins, so a name like
+/// `count_errors` isn't cut short by `count`.
 fn alternative<T>(input: T, alternatives: &[&'static str]) -> IResult<T, T>
 where
     T: InputTake,
@@ -45,15 +119,24 @@ where
     T: AtEof,
     T: Clone,
 {
+    let mut longest: Option<IResult<T, T>> = None;
     let mut last_err = None;
     for alternative in alternatives {
         let inp = input.clone();
         match tag!(inp, &**alternative) {
-            done @ Ok(..) => return done,
+            Ok((rest, matched)) => {
+                let is_longer = match longest {
+                    Some(Ok((_, ref prev))) => matched.input_len() > prev.input_len(),
+                    _ => true,
+                };
+                if is_longer {
+                    longest = Some(Ok((rest, matched)));
+                }
+            }
             err @ Err(..) => last_err = Some(err), // continue
         }
     }
-    last_err.unwrap()
+    longest.or(last_err).unwrap()
 }
 
 pub const VALID_AGGREGATES: &'static [&str] = &[
@@ -64,9 +147,13 @@ pub const VALID_AGGREGATES: &'static [&str] = &[
     "sum",
     "count_distinct",
     "sort",
+    "chart",
+    "logreduce",
 ];
 
-pub const VALID_INLINE: &'static [&str] = &["parse", "limit", "json", "total", "fields", "where"];
+pub const VALID_INLINE: &'static [&str] = &[
+    "parse", "limit", "json", "total", "fields", "where", "dedup", "rename", "fillnull", "split",
+];
 
 lazy_static! {
     pub static ref VALID_OPERATORS: Vec<&'static str> =
@@ -78,7 +165,7 @@ pub type Span<'a> = LocatedSpan<CompleteStr<'a>>;
 
 /// Container for the position of some syntax in the input string.  This is similar to the Span,
 /// but it only contains the offset.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct QueryPosition(pub usize);
 
 impl<'a> From<Span<'a>> for QueryPosition {
@@ -88,7 +175,7 @@ impl<'a> From<Span<'a>> for QueryPosition {
 }
 
 /// Container for values from the query that records the location in the query string.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Positioned<T> {
     pub start_pos: QueryPosition,
     pub end_pos: QueryPosition,
@@ -134,6 +221,9 @@ pub enum Expr {
         right: Box<Expr>,
     },
     Value(data::Value),
+    /// A `$name` placeholder for a value that's given when the query is run.  Parameters are
+    /// replaced by their values when the query is parsed.
+    Param(Positioned<String>),
 }
 
 /// The KeywordType determines how a keyword string should be interpreted.
@@ -167,6 +257,29 @@ impl Keyword {
 
     /// Convert this keyword to a `regex::Regex` object.
     pub fn to_regex(&self) -> regex::Regex {
+        regex::Regex::new(&self.regex_str()).unwrap()
+    }
+
+    /// Convert this keyword to a `regex::bytes::Regex` object that can search for the keyword in
+    /// a buffer of many lines.  The buffer doesn't have to be valid UTF-8.
+    pub fn to_bytes_regex(&self) -> regex::bytes::Regex {
+        regex::bytes::Regex::new(&format!("(?m){}", self.regex_str())).unwrap()
+    }
+
+    /// Build a single regex that finds any of the keywords in a line, for highlighting them.
+    /// Returns `None` if there aren't any keywords.
+    pub fn any_regex(keywords: &[Keyword]) -> Option<regex::Regex> {
+        if keywords.is_empty() {
+            return None;
+        }
+        let alternatives: Vec<String> = keywords
+            .iter()
+            .map(|keyword| format!("(?:{})", keyword.regex_str()))
+            .collect();
+        regex::Regex::new(&alternatives.join("|")).ok()
+    }
+
+    fn regex_str(&self) -> String {
         let mut regex_str = regex::escape(&self.0.replace("\\\"", "\""));
 
         regex_str.insert_str(0, "(?i)");
@@ -177,8 +290,7 @@ impl Keyword {
                 regex_str.push('$');
             }
         }
-
-        regex::Regex::new(&regex_str).unwrap()
+        regex_str
     }
 }
 
@@ -187,6 +299,11 @@ pub enum Operator {
     Inline(Positioned<InlineOperator>),
     MultiAggregate(MultiAggregateOperator),
     Sort(SortOperator),
+    Chart(ChartOperator),
+    LogReduce(Positioned<LogReduceOperator>),
+    /// A `@name` macro that wasn't expanded.  Only queries parsed without a config, like the
+    /// ones given to `--fmt`, keep these; they can't be run.
+    Macro(Positioned<String>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -216,6 +333,52 @@ pub enum InlineOperator {
         input_column: Expr,
         output_column: String,
     },
+    Rename {
+        /// The old and new name of each column.
+        columns: Vec<(String, String)>,
+    },
+    FillNull {
+        value: Expr,
+        /// The columns to fill in if they're missing.  Without columns, only the values that are
+        /// none are replaced.
+        columns: Vec<String>,
+    },
+    Split {
+        column: String,
+        separator: Option<Positioned<String>>,
+        /// The column for the elements, if it isn't the column that's split.
+        output_column: Option<String>,
+    },
+    Dedup {
+        key_cols: Vec<Expr>,
+        keep: Keep,
+        /// A window of zero records is rejected in the next phase.
+        within: Option<Positioned<usize>>,
+    },
+    /// An operator from the registry.
+    Custom {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+impl InlineOperator {
+    /// The name the operator is written as in a query.
+    pub fn name(&self) -> &str {
+        match *self {
+            InlineOperator::Json { .. } => "json",
+            InlineOperator::Parse { .. } => "parse",
+            InlineOperator::Fields { .. } => "fields",
+            InlineOperator::Where { .. } => "where",
+            InlineOperator::Limit { .. } => "limit",
+            InlineOperator::Total { .. } => "total",
+            InlineOperator::Rename { .. } => "rename",
+            InlineOperator::FillNull { .. } => "fillnull",
+            InlineOperator::Split { .. } => "split",
+            InlineOperator::Dedup { .. } => "dedup",
+            InlineOperator::Custom { ref name, .. } => name,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -224,6 +387,13 @@ pub enum FieldMode {
     Except,
 }
 
+/// Which of the records with the same key `dedup` keeps.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Keep {
+    First,
+    Last,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum SortMode {
     Ascending,
@@ -247,6 +417,25 @@ pub enum AggregateFunction {
     CountDistinct {
         column: Option<Positioned<Vec<Expr>>>,
     },
+    /// An aggregate function from the registry.
+    Custom {
+        name: String,
+        args: Vec<Expr>,
+    },
+}
+
+impl AggregateFunction {
+    /// The name the function is written as in a query.
+    pub fn name(&self) -> &str {
+        match *self {
+            AggregateFunction::Count => "count",
+            AggregateFunction::Sum { .. } => "sum",
+            AggregateFunction::Average { .. } => "average",
+            AggregateFunction::Percentile { .. } => "percentile",
+            AggregateFunction::CountDistinct { .. } => "count_distinct",
+            AggregateFunction::Custom { ref name, .. } => name,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -262,12 +451,377 @@ pub struct SortOperator {
     pub direction: SortMode,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct ChartOperator {
+    /// The column to chart, the last column if not given.
+    pub column: Option<String>,
+}
+
+/// Groups lines into templates, with the parts that vary between lines, like numbers and ids,
+/// masked out.
+#[derive(Debug, PartialEq)]
+pub struct LogReduceOperator {
+    /// The column to group instead of the raw line.
+    pub input_column: Option<Expr>,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Query {
     pub search: Vec<Keyword>,
     pub operators: Vec<Operator>,
 }
 
+impl Query {
+    /// The expressions in the operators, not including the ones nested inside them.
+    pub fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        let mut exprs = Vec::new();
+        for op in &mut self.operators {
+            match op {
+                Operator::Inline(op) => match op.value {
+                    InlineOperator::Parse {
+                        input_column: Some(ref mut expr),
+                        ..
+                    }
+                    | InlineOperator::Where {
+                        expr:
+                            Some(Positioned {
+                                value: ref mut expr,
+                                ..
+                            }),
+                    }
+                    | InlineOperator::Total {
+                        input_column: ref mut expr,
+                        ..
+                    }
+                    | InlineOperator::FillNull {
+                        value: ref mut expr,
+                        ..
+                    } => exprs.push(expr),
+                    InlineOperator::Custom { ref mut args, .. }
+                    | InlineOperator::Dedup {
+                        key_cols: ref mut args,
+                        ..
+                    } => exprs.extend(args),
+                    _ => (),
+                },
+                Operator::MultiAggregate(agg) => {
+                    exprs.extend(&mut agg.key_cols);
+                    for (_, function) in &mut agg.aggregate_functions {
+                        match function.value {
+                            AggregateFunction::Sum { ref mut column }
+                            | AggregateFunction::Average { ref mut column }
+                            | AggregateFunction::Percentile { ref mut column, .. } => {
+                                exprs.push(column)
+                            }
+                            AggregateFunction::CountDistinct {
+                                column: Some(ref mut columns),
+                            } => exprs.extend(&mut columns.value),
+                            AggregateFunction::Custom { ref mut args, .. } => exprs.extend(args),
+                            _ => (),
+                        }
+                    }
+                }
+                Operator::LogReduce(Positioned {
+                    value:
+                        LogReduceOperator {
+                            input_column: Some(ref mut expr),
+                        },
+                    ..
+                }) => exprs.push(expr),
+                Operator::Sort(_)
+                | Operator::Chart(_)
+                | Operator::LogReduce(_)
+                | Operator::Macro(_) => (),
+            }
+        }
+        exprs
+    }
+}
+
+/// Writes the keyword the way it's written in a query, quoted if it's exact.
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.1 {
+            KeywordType::EXACT => write_quoted(f, &self.0),
+            KeywordType::WILDCARD => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Queries are displayed in a canonical form: aliases like `avg` and `dsc` are written as
+/// `average` and `desc`, defaults like `as _count` are left out and there's a single space
+/// between tokens.  Comments aren't kept.  The alternate form, `{:#}`, puts each operator on its
+/// own line.  Parsing the canonical form gives the same query back.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.search.is_empty() {
+            write!(f, "*")?;
+        }
+        for (i, keyword) in self.search.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", keyword)?;
+        }
+        let separator = if f.alternate() { "\n  | " } else { " | " };
+        for op in &self.operators {
+            write!(f, "{}{}", separator, op)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operator::Inline(ref op) => write!(f, "{}", op.value),
+            Operator::MultiAggregate(ref op) => {
+                for (i, (output, function)) in op.aggregate_functions.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", function.value)?;
+                    if *output != default_output(function) {
+                        write!(f, " as {}", output)?;
+                    }
+                }
+                // The headers are the key expressions as they were written.  They're used as the
+                // column names, so they're kept as they are instead of being formatted.
+                if !op.key_col_headers.is_empty() {
+                    write!(f, " by {}", op.key_col_headers.join(", "))?;
+                }
+                Ok(())
+            }
+            Operator::Sort(ref op) => {
+                write!(f, "sort")?;
+                if !op.sort_cols.is_empty() {
+                    write!(f, " by {}", op.sort_cols.join(", "))?;
+                }
+                match op.direction {
+                    SortMode::Ascending => Ok(()),
+                    SortMode::Descending => write!(f, " desc"),
+                }
+            }
+            Operator::Chart(ref op) => match op.column {
+                Some(ref column) => write!(f, "chart {}", column),
+                None => write!(f, "chart"),
+            },
+            Operator::LogReduce(ref op) => match op.value.input_column {
+                Some(ref column) => write!(f, "logreduce from {}", column),
+                None => write!(f, "logreduce"),
+            },
+            Operator::Macro(ref name) => write!(f, "@{}", name.value),
+        }
+    }
+}
+
+impl fmt::Display for InlineOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InlineOperator::Json { ref input_column } => match input_column {
+                Some(column) => write!(f, "json from {}", column),
+                None => write!(f, "json"),
+            },
+            InlineOperator::Parse {
+                ref pattern,
+                ref fields,
+                ref input_column,
+                no_drop,
+            } => {
+                write!(f, "parse ")?;
+                write_quoted(f, &pattern.0)?;
+                if let Some(column) = input_column {
+                    write!(f, " from {}", column)?;
+                }
+                write!(f, " as {}", fields.join(", "))?;
+                if no_drop {
+                    write!(f, " nodrop")?;
+                }
+                Ok(())
+            }
+            InlineOperator::Fields {
+                ref mode,
+                ref fields,
+            } => match mode {
+                FieldMode::Only => write!(f, "fields {}", fields.join(", ")),
+                FieldMode::Except => write!(f, "fields except {}", fields.join(", ")),
+            },
+            InlineOperator::Where { ref expr } => match expr {
+                Some(expr) => write!(f, "where {}", expr.value),
+                None => write!(f, "where"),
+            },
+            InlineOperator::Limit { ref count } => match count {
+                Some(count) => write!(f, "limit {}", count.value),
+                None => write!(f, "limit"),
+            },
+            InlineOperator::Total {
+                ref input_column,
+                ref output_column,
+            } => {
+                write!(f, "total({})", input_column)?;
+                if output_column != "_total" {
+                    write!(f, " as {}", output_column)?;
+                }
+                Ok(())
+            }
+            InlineOperator::Rename { ref columns } => {
+                let columns: Vec<String> = columns
+                    .iter()
+                    .map(|(old, new)| format!("{} as {}", old, new))
+                    .collect();
+                write!(f, "rename {}", columns.join(", "))
+            }
+            InlineOperator::FillNull {
+                ref value,
+                ref columns,
+            } => {
+                write!(f, "fillnull")?;
+                if *value != default_fill() {
+                    write!(f, " with {}", value)?;
+                }
+                if !columns.is_empty() {
+                    write!(f, " {}", columns.join(", "))?;
+                }
+                Ok(())
+            }
+            InlineOperator::Split {
+                ref column,
+                ref separator,
+                ref output_column,
+            } => {
+                write!(f, "split {}", column)?;
+                if let Some(separator) = separator {
+                    write!(f, " on ")?;
+                    write_quoted(f, &separator.value)?;
+                }
+                if let Some(output_column) = output_column {
+                    write!(f, " as {}", output_column)?;
+                }
+                Ok(())
+            }
+            InlineOperator::Dedup {
+                ref key_cols,
+                ref keep,
+                ref within,
+            } => {
+                write!(f, "dedup")?;
+                if !key_cols.is_empty() {
+                    let key_cols: Vec<String> = key_cols.iter().map(Expr::to_string).collect();
+                    write!(f, " {}", key_cols.join(", "))?;
+                }
+                if *keep == Keep::Last {
+                    write!(f, " keep=last")?;
+                }
+                if let Some(within) = within {
+                    write!(f, " within={}", within.value)?;
+                }
+                Ok(())
+            }
+            InlineOperator::Custom { ref name, ref args } => write_call(f, name, args),
+        }
+    }
+}
+
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AggregateFunction::Count => write!(f, "count"),
+            AggregateFunction::Sum { ref column } => write!(f, "sum({})", column),
+            AggregateFunction::Average { ref column } => write!(f, "average({})", column),
+            AggregateFunction::Percentile {
+                ref percentile_str,
+                ref column,
+                ..
+            } => write!(f, "p{}({})", percentile_str, column),
+            AggregateFunction::CountDistinct { ref column } => match column {
+                Some(columns) => write_call(f, "count_distinct", &columns.value),
+                None => write!(f, "count_distinct"),
+            },
+            AggregateFunction::Custom { ref name, ref args } => write_call(f, name, args),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Column(ref name) => write!(f, "{}", name),
+            Expr::Unary {
+                op: UnaryOp::Not,
+                ref operand,
+            } => {
+                write!(f, "!")?;
+                write_operand(f, operand)
+            }
+            Expr::Binary {
+                op: BinaryOp::Comparison(ref op),
+                ref left,
+                ref right,
+            } => {
+                write_operand(f, left)?;
+                write!(f, " {} ", op)?;
+                write_operand(f, right)
+            }
+            Expr::Value(data::Value::Str(ref s)) => write_quoted(f, s),
+            Expr::Value(ref value) => write!(f, "{}", value),
+            Expr::Param(ref name) => write!(f, "${}", name.value),
+        }
+    }
+}
+
+impl fmt::Display for ComparisonOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match *self {
+            ComparisonOp::Eq => "==",
+            ComparisonOp::Neq => "!=",
+            ComparisonOp::Gt => ">",
+            ComparisonOp::Lt => "<",
+            ComparisonOp::Gte => ">=",
+            ComparisonOp::Lte => "<=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// Operands of an operator are wrapped in parentheses unless they're a column or a value.
+fn write_operand(f: &mut fmt::Formatter, expr: &Expr) -> fmt::Result {
+    match *expr {
+        Expr::Unary { .. } | Expr::Binary { .. } => write!(f, "({})", expr),
+        _ => write!(f, "{}", expr),
+    }
+}
+
+/// Write an operator or function with its arguments, leaving out the parentheses if there are
+/// none.
+fn write_call(f: &mut fmt::Formatter, name: &str, args: &[Expr]) -> fmt::Result {
+    write!(f, "{}", name)?;
+    if !args.is_empty() {
+        let args: Vec<String> = args.iter().map(Expr::to_string).collect();
+        write!(f, "({})", args.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Write a string as it was in the query, with its escapes.  Double quotes are used unless
+/// the string has a double quote in it that isn't escaped.
+fn write_quoted(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    let mut escaped = false;
+    let mut double_quote = false;
+    for c in s.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => double_quote = true,
+            _ => (),
+        }
+    }
+    if double_quote {
+        write!(f, "'{}'", s)
+    } else {
+        write!(f, "\"{}\"", s)
+    }
+}
+
 fn is_ident(c: char) -> bool {
     is_alphanumeric(c as u8) || c == '_'
 }
@@ -296,10 +850,11 @@ fn not_escape_sq(c: char) -> bool {
 fn not_escape_dq(c: char) -> bool {
     c != '\\' && c != '\"'
 }
-named!(value<Span, data::Value>, ws!(
+named!(value<Span, Expr>, wsc!(
     alt!(
-        map!(quoted_string, |s|data::Value::Str(s.to_string()))
-        | map!(digit1, |s|data::Value::from_string(s.fragment.0))
+        map!(quoted_string, |s|Expr::Value(data::Value::Str(s.to_string())))
+        | map!(digit1, |s|Expr::Value(data::Value::from_string(s.fragment.0)))
+        | map!(with_pos!(preceded!(tag!("$"), ident)), Expr::Param)
     )
 ));
 named!(ident<Span, String>, do_parse!(
@@ -309,23 +864,24 @@ named!(ident<Span, String>, do_parse!(
 ));
 
 named!(e_ident<Span, Expr>,
-    ws!(alt!(
+    wsc!(alt!(
       map!(ident, |col|Expr::Column(col.to_owned()))
-    | map!(value, Expr::Value)
+    | value
       //expr
-    | ws!(add_return_error!(SyntaxErrors::StartOfError.into(), delimited!(
+    | wsc!(add_return_error!(SyntaxErrors::StartOfError.into(), delimited!(
           tag!("("),
           expr,
           return_error!(SyntaxErrors::MissingParen.into(), tag!(")")))))
 )));
 
 named!(keyword<Span, String>, do_parse!(
+    not!(comment) >>
     start: take_while1!(is_keyword) >>
     rest: take_while!(is_keyword) >>
     (start.fragment.0.to_owned() + rest.fragment.0)
 ));
 
-named!(comp_op<Span, ComparisonOp>, ws!(alt!(
+named!(comp_op<Span, ComparisonOp>, wsc!(alt!(
     map!(tag!("=="), |_|ComparisonOp::Eq)
     | map!(tag!("<="), |_|ComparisonOp::Lte)
     | map!(tag!(">="), |_|ComparisonOp::Gte)
@@ -334,11 +890,11 @@ named!(comp_op<Span, ComparisonOp>, ws!(alt!(
     | map!(tag!("<"), |_|ComparisonOp::Lt)
 )));
 
-named!(unary_op<Span, UnaryOp>, ws!(alt!(
+named!(unary_op<Span, UnaryOp>, wsc!(alt!(
     map!(tag!("!"), |_|UnaryOp::Not)
 )));
 
-named!(expr<Span, Expr>, ws!(alt!(
+named!(expr<Span, Expr>, wsc!(alt!(
     do_parse!(
         l: e_ident >>
         comp: comp_op >>
@@ -353,19 +909,19 @@ named!(expr<Span, Expr>, ws!(alt!(
     | e_ident
 )));
 
-named!(json<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
+named!(json<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
     tag!("json") >>
-    from_column_opt: opt!(ws!(preceded!(tag!("from"), ident))) >>
+    from_column_opt: opt!(wsc!(preceded!(tag!("from"), ident))) >>
     (InlineOperator::Json { input_column: from_column_opt.map(|s|s.to_string()) })
 ))));
 
-named!(whre<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
+named!(whre<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
     tag!("where") >>
     ex: opt!(with_pos!(expr)) >>
     (InlineOperator::Where { expr: ex })
 ))));
 
-named!(limit<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
+named!(limit<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
     tag!("limit") >>
     count: opt!(with_pos!(double)) >>
     (InlineOperator::Limit{
@@ -373,16 +929,82 @@ named!(limit<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
     })
 ))));
 
-named!(total<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
+named!(total<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
     tag!("total") >>
     input_column: delimited!(tag!("("), expr, tag!(")")) >>
-    rename_opt: opt!(ws!(preceded!(tag!("as"), ident))) >>
+    rename_opt: opt!(wsc!(preceded!(tag!("as"), ident))) >>
     (InlineOperator::Total{
         input_column,
         output_column:
             rename_opt.map(|s|s.to_string()).unwrap_or_else(||"_total".to_string()),
 })))));
 
+// rename a as b, c as d
+named!(rename<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
+    tag!("rename") >>
+    columns: wsc!(separated_nonempty_list!(tag!(","), wsc!(do_parse!(
+        old: ident >>
+        tag!("as") >>
+        new: ident >>
+        ((old, new))
+    )))) >>
+    (InlineOperator::Rename { columns })
+))));
+
+/// The value `fillnull` uses if none is given.
+fn default_fill() -> Expr {
+    Expr::Value(data::Value::Int(0))
+}
+
+// fillnull [with value] [a, b]
+named!(fillnull<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
+    tag!("fillnull") >>
+    fill: opt!(wsc!(preceded!(tag!("with"), value))) >>
+    columns: opt!(var_list) >>
+    (InlineOperator::FillNull {
+        value: fill.unwrap_or_else(default_fill),
+        columns: columns.unwrap_or_default()
+    })
+))));
+
+// split a [on ","] [as b]
+named!(split<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
+    tag!("split") >>
+    column: ident >>
+    separator: opt!(wsc!(preceded!(tag!("on"), with_pos!(map!(quoted_string, str::to_string))))) >>
+    output_column: opt!(wsc!(preceded!(tag!("as"), ident))) >>
+    (InlineOperator::Split {
+        column,
+        separator,
+        output_column
+    })
+))));
+
+// A single `=`, so that `keep == x` is still a comparison
+named!(option_eq<Span, Span>, terminated!(tag!("="), not!(tag!("="))));
+
+named!(dedup_option<Span, Span>, wsc!(terminated!(alt!(tag!("keep") | tag!("within")), option_eq)));
+
+named!(keep<Span, Keep>, wsc!(preceded!(
+    wsc!(terminated!(tag!("keep"), option_eq)),
+    alt!(map!(tag!("first"), |_|Keep::First) | map!(tag!("last"), |_|Keep::Last))
+)));
+
+// dedup [a, b] [keep=first|last] [within=N]
+named!(dedup<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
+    tag!("dedup") >>
+    key_cols: wsc!(separated_list!(tag!(","), wsc!(preceded!(not!(dedup_option), expr)))) >>
+    keep: opt!(keep) >>
+    within: opt!(wsc!(preceded!(wsc!(terminated!(tag!("within"), option_eq)), with_pos!(
+        map_res!(digit1, |count: Span| count.fragment.0.parse::<usize>())
+    )))) >>
+    (InlineOperator::Dedup {
+        key_cols,
+        keep: keep.unwrap_or(Keep::First),
+        within
+    })
+))));
+
 named!(double_quoted_string <Span, &str>, add_return_error!(
     SyntaxErrors::StartOfError.into(), delimited!(
         tag!("\""),
@@ -399,19 +1021,19 @@ named!(single_quoted_string <Span, &str>, add_return_error!(
 
 named!(quoted_string<Span, &str>, alt!(double_quoted_string | single_quoted_string));
 
-named!(var_list<Span, Vec<String> >, ws!(separated_nonempty_list!(
-    tag!(","), ws!(ident)
+named!(var_list<Span, Vec<String> >, wsc!(separated_nonempty_list!(
+    tag!(","), wsc!(ident)
 )));
 
-named!(sourced_expr_list<Span, Vec<(String, Expr)> >, ws!(separated_nonempty_list!(
-    tag!(","), ws!(sourced_expr)
+named!(sourced_expr_list<Span, Vec<(String, Expr)> >, wsc!(separated_nonempty_list!(
+    tag!(","), wsc!(sourced_expr)
 )));
 
-named!(sourced_expr<Span, (String, Expr)>, ws!(
+named!(sourced_expr<Span, (String, Expr)>, wsc!(
     do_parse!(
         ex: recognize!(expr) >>
         (
-            (ex.fragment.0.trim().to_string(), expr(ex).unwrap().1)
+            (trim_end_comments(ex.fragment.0.trim_start()).to_string(), expr(ex).unwrap().1)
         )
 )));
 
@@ -431,21 +1053,21 @@ named_args!(did_you_mean<'a>(choices: &[&'static str], err: SyntaxErrors)<Span<'
 );
 
 named!(did_you_mean_operator<Span, Span>,
-    call!(did_you_mean, &VALID_OPERATORS, SyntaxErrors::NotAnOperator)
+    call!(did_you_mean, &registry::valid_operators(), SyntaxErrors::NotAnOperator)
 );
 
 named!(did_you_mean_aggregate<Span, Span>,
-    call!(did_you_mean, &VALID_AGGREGATES, SyntaxErrors::NotAnAggregateOperator)
+    call!(did_you_mean, &registry::valid_aggregates(), SyntaxErrors::NotAnAggregateOperator)
 );
 
 // parse "blah * ... *" [from other_field] as x, y
-named!(parse<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
+named!(parse<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
     tag!("parse") >>
     pattern: quoted_string >>
-    from_column_opt: opt!(ws!(preceded!(tag!("from"), expr))) >>
+    from_column_opt: opt!(wsc!(preceded!(tag!("from"), expr))) >>
     tag!("as") >>
     vars: var_list >>
-    no_drop_opt: opt!(ws!(tag!("nodrop"))) >>
+    no_drop_opt: opt!(wsc!(tag!("nodrop"))) >>
     ( InlineOperator::Parse{
         pattern: Keyword::new_wildcard(pattern.to_string()),
         fields: vars,
@@ -465,7 +1087,7 @@ named!(fields_mode<Span, FieldMode>, alt!(
     )
 ));
 
-named!(fields<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
+named!(fields<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
     tag!("fields") >>
     mode: opt!(fields_mode) >>
     fields: var_list >>
@@ -480,7 +1102,7 @@ named!(fields<Span, Positioned<InlineOperator>>, with_pos!(ws!(do_parse!(
 named!(arg_list<Span, Positioned<Vec<Expr>>>, add_return_error!(
     SyntaxErrors::StartOfError.into(), with_pos!(delimited!(
         tag!("("),
-        ws!(separated_list!(tag!(","), ws!(expr))),
+        wsc!(separated_list!(tag!(","), wsc!(expr))),
         return_error!(SyntaxErrors::MissingParen.into(), tag!(")"))))
 ));
 
@@ -488,19 +1110,19 @@ named!(count<Span, Positioned<AggregateFunction>>, with_pos!(map!(tag!("count"),
     |_s|AggregateFunction::Count{}))
 );
 
-named!(average<Span, Positioned<AggregateFunction>>, with_pos!(ws!(do_parse!(
+named!(average<Span, Positioned<AggregateFunction>>, with_pos!(wsc!(do_parse!(
     alt!(tag!("avg") | tag!("average")) >>
     column: delimited!(tag!("("), expr ,tag!(")")) >>
     (AggregateFunction::Average{column})
 ))));
 
-named!(count_distinct<Span, Positioned<AggregateFunction>>, with_pos!(ws!(do_parse!(
+named!(count_distinct<Span, Positioned<AggregateFunction>>, with_pos!(wsc!(do_parse!(
     tag!("count_distinct") >>
     column: opt!(arg_list) >>
     (AggregateFunction::CountDistinct{ column })
 ))));
 
-named!(sum<Span, Positioned<AggregateFunction>>, with_pos!(ws!(do_parse!(
+named!(sum<Span, Positioned<AggregateFunction>>, with_pos!(wsc!(do_parse!(
     tag!("sum") >>
     column: delimited!(tag!("("), expr,tag!(")")) >>
     (AggregateFunction::Sum{column})
@@ -515,7 +1137,7 @@ named!(pct_fn<Span, Span>, preceded!(
     take_while_m_n!(2, 2, is_digit_char)
 ));
 
-named!(p_nn<Span, Positioned<AggregateFunction>>, ws!(
+named!(p_nn<Span, Positioned<AggregateFunction>>, wsc!(
     with_pos!(do_parse!(
         pct: pct_fn >>
         column: delimited!(tag!("("), expr,tag!(")")) >>
@@ -527,13 +1149,28 @@ named!(p_nn<Span, Positioned<AggregateFunction>>, ws!(
     ))
 ));
 
+// my_operator [(arg, ...)]
+named!(custom_inline<Span, Positioned<InlineOperator>>, with_pos!(wsc!(do_parse!(
+    name: map_opt!(ident, |name: String| registry::inline_factory(&name).map(|_| name)) >>
+    args: opt!(arg_list) >>
+    (InlineOperator::Custom { name, args: args.map(|args| args.value).unwrap_or_default() })
+))));
+
+// my_aggregate [(arg, ...)]
+named!(custom_aggregate<Span, Positioned<AggregateFunction>>, with_pos!(wsc!(do_parse!(
+    name: map_opt!(ident, |name: String| registry::aggregate_factory(&name).map(|_| name)) >>
+    args: opt!(arg_list) >>
+    (AggregateFunction::Custom { name, args: args.map(|args| args.value).unwrap_or_default() })
+))));
+
 named!(inline_operator<Span, Operator>,
-    map!(alt!(parse | json | fields | whre | limit | total), Operator::Inline)
+    map!(alt!(parse | json | fields | whre | limit | total | rename | fillnull | split | dedup), Operator::Inline)
 );
 
 named!(aggregate_function<Span, Positioned<AggregateFunction>>, do_parse!(
     peek!(did_you_mean_aggregate) >>
     res: alt_complete!(
+        custom_aggregate |
         count_distinct |
         count |
         average |
@@ -541,9 +1178,32 @@ named!(aggregate_function<Span, Positioned<AggregateFunction>>, do_parse!(
         p_nn) >> (res)
 ));
 
-named!(operator<Span, Operator>, do_parse!(
-    peek!(did_you_mean_operator) >>
-    res: alt_complete!(inline_operator | sort | multi_aggregate_operator) >> (res)
+fn is_macro_name(c: char) -> bool {
+    is_ident(c) || c == '-'
+}
+
+named!(macro_reference<Span, Operator>, map!(
+    terminated!(with_pos!(preceded!(tag!("@"), take_while1!(is_macro_name))), space_or_comment),
+    |name| Operator::Macro(Positioned {
+        start_pos: name.start_pos,
+        value: name.value.fragment.0.to_string(),
+        end_pos: name.end_pos,
+    })
+));
+
+// Registered names are tried before the built-in operators, whose tags would otherwise match the
+// start of a name like `count_errors`.  A built-in name can't be registered, so nothing is shadowed.
+named!(custom_operator<Span, Operator>, alt!(
+    map!(custom_inline, Operator::Inline) |
+    do_parse!(peek!(custom_aggregate) >> res: multi_aggregate_operator >> (res))
+));
+
+named!(operator<Span, Operator>, alt!(
+    macro_reference |
+    do_parse!(
+        peek!(did_you_mean_operator) >>
+        res: alt_complete!(custom_operator | inline_operator | sort | chart | logreduce | multi_aggregate_operator) >> (res)
+    )
 ));
 
 // count by x,y
@@ -558,12 +1218,13 @@ fn default_output(func: &Positioned<AggregateFunction>) -> String {
         AggregateFunction::Percentile {
             ref percentile_str, ..
         } => "p".to_string() + percentile_str,
+        AggregateFunction::Custom { ref name, .. } => "_".to_string() + name,
     }
 }
 
-named!(complete_agg_function<Span, (String, Positioned<AggregateFunction>)>, ws!(do_parse!(
+named!(complete_agg_function<Span, (String, Positioned<AggregateFunction>)>, wsc!(do_parse!(
         agg_function: aggregate_function >>
-        rename_opt: opt!(ws!(preceded!(tag!("as"), ident))) >>
+        rename_opt: opt!(wsc!(preceded!(tag!("as"), ident))) >>
         (
             rename_opt.map(|s|s.to_string()).unwrap_or_else(||default_output(&agg_function)),
             agg_function
@@ -571,8 +1232,8 @@ named!(complete_agg_function<Span, (String, Positioned<AggregateFunction>)>, ws!
     ))
 );
 
-named!(multi_aggregate_operator<Span, Operator>, ws!(do_parse!(
-    agg_functions: ws!(separated_nonempty_list!(tag!(","), complete_agg_function)) >>
+named!(multi_aggregate_operator<Span, Operator>, wsc!(do_parse!(
+    agg_functions: wsc!(separated_nonempty_list!(tag!(","), complete_agg_function)) >>
     key_cols_opt: opt!(preceded!(tag!("by"), sourced_expr_list)) >>
     (Operator::MultiAggregate(MultiAggregateOperator {
         key_col_headers: key_cols_opt.clone()
@@ -596,7 +1257,7 @@ named!(sort_mode<Span, SortMode>, alt!(
     )
 ));
 
-named!(sort<Span, Operator>, ws!(do_parse!(
+named!(sort<Span, Operator>, wsc!(do_parse!(
     tag!("sort") >>
     key_cols_opt: opt!(preceded!(opt!(tag!("by")), var_list)) >>
     dir: opt!(sort_mode) >>
@@ -606,6 +1267,20 @@ named!(sort<Span, Operator>, ws!(do_parse!(
      })))
 ));
 
+// chart [column]
+named!(chart<Span, Operator>, wsc!(do_parse!(
+    tag!("chart") >>
+    column: opt!(ident) >>
+    (Operator::Chart(ChartOperator { column }))
+)));
+
+// logreduce [from column]
+named!(logreduce<Span, Operator>, map!(with_pos!(wsc!(do_parse!(
+    tag!("logreduce") >>
+    input_column: opt!(wsc!(preceded!(tag!("from"), expr))) >>
+    (LogReduceOperator { input_column })
+))), Operator::LogReduce));
+
 named!(filter_cond<Span, Keyword>, alt!(
     map!(quoted_string, |s| Keyword::new_exact(s.to_string())) |
     map!(keyword, |s| Keyword::new_wildcard(s.trim_matches('*').to_string()))
@@ -620,9 +1295,9 @@ named!(filter<Span, Vec<Keyword>>, map!(
     }
 ));
 
-named!(pub query<Span, Query, SyntaxErrors>, fix_error!(SyntaxErrors, exact!(ws!(do_parse!(
+named!(pub query<Span, Query, SyntaxErrors>, fix_error!(SyntaxErrors, exact!(wsc!(do_parse!(
     filter: filter >>
-    operators: opt!(preceded!(tag!("|"), ws!(separated_nonempty_list!(tag!("|"), operator)))) >>
+    operators: opt!(preceded!(tag!("|"), wsc!(separated_nonempty_list!(tag!("|"), operator)))) >>
     (Query{
         search: filter,
         operators: operators.unwrap_or_default()
@@ -705,6 +1380,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_expr_param() {
+        expect!(
+            expr,
+            "a > $threshold",
+            Expr::Binary {
+                op: BinaryOp::Comparison(ComparisonOp::Gt),
+                left: Box::new(Expr::Column("a".to_string())),
+                right: Box::new(Expr::Param(Positioned {
+                    start_pos: QueryPosition(4),
+                    end_pos: QueryPosition(14),
+                    value: "threshold".to_string(),
+                })),
+            }
+        );
+    }
+
     #[test]
     fn parse_expr_ident() {
         expect!(expr, "foo", Expr::Column("foo".to_string()));
@@ -997,4 +1689,238 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn query_comments() {
+        let query_str = "# errors by host\n#tag # search\n  | json  # parse it\n  # count\n  | count by host # \"x\"\n";
+        expect!(
+            query,
+            query_str,
+            Query {
+                search: vec![Keyword::new_wildcard("#tag".to_string())],
+                operators: vec![
+                    Operator::Inline(Positioned {
+                        start_pos: QueryPosition(35),
+                        end_pos: QueryPosition(64),
+                        value: InlineOperator::Json { input_column: None },
+                    }),
+                    Operator::MultiAggregate(MultiAggregateOperator {
+                        key_col_headers: vec!["host".to_string()],
+                        key_cols: vec![Expr::Column("host".to_string())],
+                        aggregate_functions: vec![(
+                            "_count".to_string(),
+                            Positioned {
+                                value: AggregateFunction::Count {},
+                                start_pos: QueryPosition(66),
+                                end_pos: QueryPosition(71),
+                            }
+                        )],
+                    }),
+                ],
+            }
+        );
+        assert_eq!(trim_end_comments("x == \"# a\"  # b\n "), "x == \"# a\"");
+    }
+
+    fn parse_all(text: &str) -> Query {
+        match query(Span::new(CompleteStr(text))) {
+            Ok((rest, parsed)) if rest.fragment.0.is_empty() => parsed,
+            other => panic!("Failed to parse {}: {:?}", text, other),
+        }
+    }
+
+    #[test]
+    fn format_query() {
+        let canonical = &[
+            "*",
+            r#"error "two words" | json from raw | count by host"#,
+            r#"* | parse "* *" from msg as a, b nodrop | fields except a | where !(a >= 5)"#,
+            r#"* | parse 'say "*"' as word | where word != "x\"y" | limit 5"#,
+            "* | json | average(x), p95(x) as p, count_distinct(a, b) by y | sort by p desc",
+            "* | json | where level == $level | total(x) as running | chart",
+            "* | json | logreduce from message | limit 5",
+            "* | json | dedup request_id, status keep=last within=100",
+            r#"* | json | rename level as severity, msg as message | fillnull with "none" host"#,
+            r#"* | json | split tags | split hosts on ";" as host | count by host"#,
+            "@errors | @nginx-access | count by status",
+        ];
+        for text in canonical {
+            let parsed = parse_all(text);
+            assert_eq!(parsed.to_string(), *text);
+            assert_eq!(parse_all(&parsed.to_string()), parsed);
+        }
+
+        let normalized = &[
+            (
+                " *  |json|avg( x ) by y | sort by _average dsc",
+                "* | json | average(x) by y | sort by _average desc",
+            ),
+            (
+                "* | fields - a,b | fields + c | count as _count, pct50(x) as p50",
+                "* | fields except a, b | fields c | count, p50(x)",
+            ),
+            (
+                "* | fillnull with 0 | fillnull with $x a,b",
+                "* | fillnull | fillnull with $x a, b",
+            ),
+            (
+                "* | where ((a == 'b')) | total(x) as _total | sort by x asc",
+                r#"* | where a == "b" | total(x) | sort by x"#,
+            ),
+        ];
+        for (text, expected) in normalized {
+            assert_eq!(parse_all(text).to_string(), *expected);
+        }
+
+        assert_eq!(
+            format!("{:#}", parse_all("error | json | count")),
+            "error\n  | json\n  | count"
+        );
+    }
+
+    #[test]
+    fn parse_rename_fillnull() {
+        expect!(
+            operator,
+            "rename a as b, c as d",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(21),
+                value: InlineOperator::Rename {
+                    columns: vec![
+                        ("a".to_string(), "b".to_string()),
+                        ("c".to_string(), "d".to_string()),
+                    ],
+                }
+            })
+        );
+        expect!(
+            operator,
+            "fillnull without",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(16),
+                value: InlineOperator::FillNull {
+                    value: Expr::Value(data::Value::Int(0)),
+                    columns: vec!["without".to_string()],
+                }
+            })
+        );
+        expect!(
+            operator,
+            "fillnull with 'x'",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(17),
+                value: InlineOperator::FillNull {
+                    value: Expr::Value(data::Value::Str("x".to_string())),
+                    columns: vec![],
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parse_split() {
+        expect!(
+            operator,
+            "split tags",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(10),
+                value: InlineOperator::Split {
+                    column: "tags".to_string(),
+                    separator: None,
+                    output_column: None,
+                }
+            })
+        );
+        expect!(
+            operator,
+            "split hosts on ' ' as host",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(26),
+                value: InlineOperator::Split {
+                    column: "hosts".to_string(),
+                    separator: Some(Positioned {
+                        start_pos: QueryPosition(15),
+                        end_pos: QueryPosition(18),
+                        value: " ".to_string(),
+                    }),
+                    output_column: Some("host".to_string()),
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parse_dedup() {
+        expect!(
+            operator,
+            "dedup",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(5),
+                value: InlineOperator::Dedup {
+                    key_cols: vec![],
+                    keep: Keep::First,
+                    within: None,
+                }
+            })
+        );
+        expect!(
+            operator,
+            "dedup keeper, keep == x keep = last",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(35),
+                value: InlineOperator::Dedup {
+                    key_cols: vec![
+                        Expr::Column("keeper".to_string()),
+                        Expr::Binary {
+                            op: BinaryOp::Comparison(ComparisonOp::Eq),
+                            left: Box::new(Expr::Column("keep".to_string())),
+                            right: Box::new(Expr::Column("x".to_string())),
+                        },
+                    ],
+                    keep: Keep::Last,
+                    within: None,
+                }
+            })
+        );
+        expect!(
+            operator,
+            "dedup host within=10",
+            Operator::Inline(Positioned {
+                start_pos: QueryPosition(0),
+                end_pos: QueryPosition(20),
+                value: InlineOperator::Dedup {
+                    key_cols: vec![Expr::Column("host".to_string())],
+                    keep: Keep::First,
+                    within: Some(Positioned {
+                        start_pos: QueryPosition(18),
+                        end_pos: QueryPosition(20),
+                        value: 10,
+                    }),
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parse_chart() {
+        expect!(
+            chart,
+            "chart",
+            Operator::Chart(ChartOperator { column: None })
+        );
+        expect!(
+            chart,
+            "chart _count",
+            Operator::Chart(ChartOperator {
+                column: Some("_count".to_string())
+            })
+        );
+    }
 }
@@ -1,3 +1,33 @@
+//! angle-grinder parses and runs queries over logs.  Besides the `agrind` binary, the query
+//! engine can be used as a library:
+//!
+//! ```
+//! use ag::data::Value;
+//! use ag::pipeline::{parse_query, Pipeline, QueryOutput};
+//!
+//! assert!(parse_query("* | json | count by level").is_ok());
+//!
+//! let errors = Pipeline::compile("* | json | where 5").err().unwrap();
+//! assert_eq!(errors[0].message, "Expected boolean expression, found Int(5)");
+//!
+//! let pipeline = Pipeline::compile("* | json | count by level").unwrap();
+//! let lines = vec![r#"{"level": "info"}"#, r#"{"level": "info"}"#];
+//! let result = pipeline.run(lines).unwrap();
+//! assert_eq!(result.diagnostics.errors.total(), 0);
+//! match result.output {
+//!     QueryOutput::Aggregate(aggregate) => {
+//!         assert_eq!(aggregate.columns, vec!["level", "_count"]);
+//!         assert_eq!(aggregate.data[0]["_count"], Value::Int(2));
+//!     }
+//!     QueryOutput::Records(_) => unreachable!(),
+//! }
+//! ```
+//!
+//! `lang` has the syntax tree produced by `parse_query`, `data` the records and aggregates that
+//! queries produce.  `Pipeline::process_to` passes results to a `RowSink` as they are produced.
+//! Custom inline operators and aggregate functions can be added to the query language through
+//! `registry`.
+
 #[macro_use]
 extern crate failure;
 extern crate atty;
@@ -8,24 +38,56 @@ extern crate num_traits;
 extern crate annotate_snippets;
 extern crate crossbeam_channel;
 
-mod data;
+mod config;
+pub mod data;
 mod errors;
-mod lang;
-mod operator;
+mod explain;
+pub mod lang;
+pub mod operator;
+mod params;
+pub mod registry;
 mod render;
+mod schema;
+mod tui;
 mod typecheck;
 
 pub mod pipeline {
-    use crate::data::{Record, Row};
-    pub use crate::errors::{ErrorReporter, QueryContainer};
+    pub use crate::config::{Config, ConfigError};
+    use crate::data::{Aggregate, Record, Row, Value};
+    use crate::errors::ErrorBuilder;
+    pub use crate::errors::{ErrorReporter, QueryContainer, QueryError};
+    use crate::explain;
     use crate::lang::*;
     use crate::operator;
+    pub use crate::render::OutputFormat;
     use crate::render::{RenderConfig, Renderer};
+    use crate::schema::{self, ColumnCheck};
+    use crate::tui::{self, Interactive};
+    use crate::typecheck::DEFAULT_LIMIT;
     use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
     use failure::Error;
-    use std::io::BufRead;
+    use memchr::{memchr, memchr_iter, memrchr};
+    use memmap::Mmap;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read};
+    use std::iter;
+    use std::mem;
+    use std::ops::Range;
+    use std::panic;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
+
+    /// Name of the column that holds the input file of a record.
+    pub const FILE_COLUMN: &str = "_file";
+
+    /// The size that a memory-mapped input is split into before each chunk is extended to the
+    /// end of its last line.
+    const MAPPED_CHUNK_SIZE: usize = 1024 * 1024;
+
+    /// The longest a worker thread holds on to a partial aggregate before sending it on.
+    const PARTIAL_INTERVAL: Duration = Duration::from_millis(100);
 
     #[derive(Debug, Fail)]
     pub enum CompileError {
@@ -39,11 +101,319 @@ pub mod pipeline {
         Unexpected { message: String },
     }
 
+    #[derive(Debug, Fail)]
+    pub enum ProcessError {
+        #[fail(display = "Stopped at the first error: {}", message)]
+        Strict { message: String },
+
+        #[fail(display = "A worker thread failed: {}", message)]
+        Worker { message: String },
+    }
+
+    /// What to do when an operator fails on a record.  Either way the record is dropped.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ErrorMode {
+        /// Print each error as it happens and a summary once the input ends.
+        Print,
+        /// Only print the summary.
+        Summary,
+        /// Stop processing at the first error.
+        Strict,
+    }
+
     pub struct Pipeline {
-        filter: Vec<regex::Regex>,
-        pre_aggregates: Vec<Box<operator::UnaryPreAggOperator>>,
+        filter: Vec<regex::bytes::Regex>,
+        /// The search keywords as they're written in the query, for `explain`.
+        search: Vec<Keyword>,
+        /// Finds the search keywords in a line so they can be highlighted.
+        highlight: Option<regex::Regex>,
+        /// The stateless and stateful operators before the first aggregate, with their names.
+        pre_aggregates: Vec<(String, Box<operator::OperatorBuilder + Send + Sync>)>,
         aggregators: Vec<Box<operator::AggregateOperator>>,
-        renderer: Renderer,
+        output: OutputFormat,
+        num_threads: usize,
+        interactive: bool,
+        error_mode: ErrorMode,
+        /// Columns that are referenced after an operator like `json`, to check against the input.
+        unresolved_columns: Vec<String>,
+        /// Whether the query refers to the `_file` column, so it should be rendered.
+        uses_file_column: bool,
+        /// Descriptions of the pre-aggregate operators followed by the aggregate operators.
+        steps: Vec<explain::Step>,
+    }
+
+    /// Counters that are updated as the input is processed.
+    #[derive(Default)]
+    pub(crate) struct Stats {
+        lines_read: AtomicUsize,
+        lines_matched: AtomicUsize,
+        errors: Mutex<operator::ErrorCounts>,
+        /// Set when an error is counted in strict mode.
+        failed: AtomicBool,
+        print_errors: bool,
+        strict: bool,
+        columns: ColumnCheck,
+        /// Warnings that are printed with the summary, or returned to a library caller, rather
+        /// than as they happen.
+        warnings: Mutex<Vec<String>>,
+        print_warnings: bool,
+    }
+
+    impl Stats {
+        pub fn lines_read(&self) -> usize {
+            self.lines_read.load(Ordering::Relaxed)
+        }
+
+        pub fn lines_matched(&self) -> usize {
+            self.lines_matched.load(Ordering::Relaxed)
+        }
+
+        pub fn errors(&self) -> usize {
+            self.errors.lock().unwrap().total()
+        }
+
+        /// Add the errors counted by one of the threads.  Errors are counted locally and added
+        /// in bulk so the threads don't have to wait on each other for every error.
+        fn add_errors(&self, errors: &operator::ErrorCounts) {
+            if errors.is_empty() {
+                return;
+            }
+            self.errors.lock().unwrap().merge(errors);
+            if self.strict {
+                self.failed.store(true, Ordering::SeqCst);
+            }
+        }
+
+        fn failed(&self) -> bool {
+            self.failed.load(Ordering::SeqCst)
+        }
+
+        fn strict_error(&self) -> ProcessError {
+            ProcessError::Strict {
+                message: self
+                    .errors
+                    .lock()
+                    .unwrap()
+                    .first()
+                    .unwrap_or_default()
+                    .to_string(),
+            }
+        }
+
+        /// Note the columns in a record that is partway through the operators, while the
+        /// columns are being checked.
+        fn observe_columns(&self, record: &Record) {
+            if self.columns.active() {
+                self.columns.observe(record);
+            }
+        }
+
+        /// Note the columns in a record that made it through the operators, while the columns
+        /// are being checked.
+        fn check_columns(&self, record: &Record) {
+            if self.columns.active() {
+                self.warn(self.columns.checked(record));
+            }
+        }
+
+        fn warn(&self, warnings: Vec<String>) {
+            if self.print_warnings {
+                for warning in warnings {
+                    eprintln!("warning: {}", warning);
+                }
+            } else {
+                self.warnings.lock().unwrap().extend(warnings);
+            }
+        }
+
+        fn print_summary(&self) {
+            for warning in self.warnings.lock().unwrap().iter() {
+                eprintln!("warning: {}", warning);
+            }
+            let errors = self.errors.lock().unwrap();
+            if !errors.is_empty() {
+                eprintln!("error summary:");
+                for line in errors.summary() {
+                    eprintln!("  {}", line);
+                }
+            }
+        }
+    }
+
+    /// The problems found while a query was run with `Pipeline::process_to` or
+    /// `Pipeline::run`, which are returned rather than printed.
+    #[derive(Debug, Default)]
+    pub struct Diagnostics {
+        /// Warnings about the query, like columns it refers to that no record had.
+        pub warnings: Vec<String>,
+        /// The records that operators failed on, counted by operator and kind of error.
+        pub errors: operator::ErrorCounts,
+    }
+
+    /// The results of running a query with `Pipeline::run`.
+    #[derive(Debug)]
+    pub struct QueryResult {
+        pub output: QueryOutput,
+        /// The warnings and errors from the run, which aren't printed.
+        pub diagnostics: Diagnostics,
+    }
+
+    /// What a query run with `Pipeline::run` produced.
+    #[derive(Debug, PartialEq)]
+    pub enum QueryOutput {
+        /// The records that made it through a query without aggregate operators.
+        Records(Vec<Record>),
+        /// The final result of a query with aggregate operators.
+        Aggregate(Aggregate),
+    }
+
+    /// Parse a query into its syntax tree.  Syntax errors are returned rather than reported.
+    pub fn parse_query(query: &str) -> Result<Query, Vec<QueryError>> {
+        let container = QueryContainer::new(query.to_string(), Box::new(SilentReporter));
+        container
+            .parse()
+            .map_err(|_pos| errors_or(&container, CompileError::Parse))
+    }
+
+    /// An `ErrorReporter` for callers that want the errors as values.
+    struct SilentReporter;
+
+    impl ErrorReporter for SilentReporter {}
+
+    /// The errors reported for the query, or `error` if none were reported.
+    fn errors_or<E: Into<Error>>(container: &QueryContainer, error: E) -> Vec<QueryError> {
+        let errors = container.errors();
+        if errors.is_empty() {
+            vec![QueryError {
+                message: error.into().to_string(),
+                annotations: Vec::new(),
+                resolutions: Vec::new(),
+            }]
+        } else {
+            errors
+        }
+    }
+
+    /// Collects the results of `Pipeline::run`.
+    #[derive(Default)]
+    struct ResultSink {
+        records: Vec<Record>,
+        aggregate: Option<Aggregate>,
+    }
+
+    impl RowSink for ResultSink {
+        fn record(&mut self, record: Record) -> Result<(), Error> {
+            self.records.push(record);
+            Ok(())
+        }
+
+        fn aggregate(&mut self, aggregate: Aggregate, _last: bool) -> Result<(), Error> {
+            self.aggregate = Some(aggregate);
+            Ok(())
+        }
+    }
+
+    /// Presents an iterator of lines as a stream.
+    struct LineReader<I> {
+        lines: I,
+        line: Vec<u8>,
+        pos: usize,
+    }
+
+    impl<S: AsRef<str>, I: Iterator<Item = S>> Read for LineReader<I> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            while self.pos == self.line.len() {
+                match self.lines.next() {
+                    Some(line) => {
+                        self.line.clear();
+                        self.line.extend_from_slice(line.as_ref().as_bytes());
+                        self.line.push(b'\n');
+                        self.pos = 0;
+                    }
+                    None => return Ok(0),
+                }
+            }
+            let len = buf.len().min(self.line.len() - self.pos);
+            buf[..len].copy_from_slice(&self.line[self.pos..self.pos + len]);
+            self.pos += len;
+            Ok(len)
+        }
+    }
+
+    /// Receives the results of a pipeline.  Without aggregate operators, every record that makes
+    /// it through the query is passed to `record`, in order.  Otherwise, `aggregate` is passed
+    /// snapshots of the aggregate while the input is being processed and the final result at
+    /// the end.
+    pub trait RowSink: Send {
+        fn record(&mut self, record: Record) -> Result<(), Error>;
+
+        /// Receive a snapshot of the aggregate.  `last` is set for the final result.
+        fn aggregate(&mut self, aggregate: Aggregate, last: bool) -> Result<(), Error>;
+
+        /// Whether the sink wants an intermediate snapshot of the aggregate now.  Computing a
+        /// snapshot isn't free, so by default only the final result is passed on.
+        fn wants_snapshot(&self) -> bool {
+            false
+        }
+    }
+
+    impl<S: RowSink + ?Sized> RowSink for Box<S> {
+        fn record(&mut self, record: Record) -> Result<(), Error> {
+            (**self).record(record)
+        }
+
+        fn aggregate(&mut self, aggregate: Aggregate, last: bool) -> Result<(), Error> {
+            (**self).aggregate(aggregate, last)
+        }
+
+        fn wants_snapshot(&self) -> bool {
+            (**self).wants_snapshot()
+        }
+    }
+
+    /// A chunk of complete input lines that is processed by one of the worker threads.
+    struct Chunk {
+        /// The position of the chunk in the input, used to restore the original order.
+        seq: usize,
+        /// The name of the input file, if the records should have a `_file` column.
+        name: Option<String>,
+        data: ChunkData,
+    }
+
+    /// The lines in a chunk, either read from a stream or a range of an input that is already in
+    /// memory, like a memory-mapped file.
+    enum ChunkData {
+        Owned(Vec<u8>),
+        Mapped(Arc<AsRef<[u8]> + Send + Sync>, Range<usize>),
+    }
+
+    impl ChunkData {
+        fn as_slice(&self) -> &[u8] {
+            match self {
+                ChunkData::Owned(data) => data,
+                ChunkData::Mapped(data, range) => &(**data).as_ref()[range.clone()],
+            }
+        }
+    }
+
+    /// An input to the pipeline.
+    pub enum Input<T> {
+        /// A stream that is read as it becomes available.
+        Stream(T),
+        /// A file that has been mapped into memory, so it can be split up without reading it.
+        Mapped(Mmap),
+        /// Lines that are already in memory and shared with the caller, like a file that is
+        /// loaded once to run several queries over.  They're split up like a mapped file.
+        Loaded(Arc<AsRef<[u8]> + Send + Sync>),
+    }
+
+    /// The result of processing a chunk on a worker thread.
+    enum Batch {
+        /// Records that still need to go through the stateful operators in their original
+        /// order, tagged with the position of their chunk.
+        Records(usize, Vec<Record>),
+        /// Records aggregated by a split copy of the first aggregate operator.
+        Partial(Box<operator::AggregateOperator>),
     }
 
     impl Pipeline {
@@ -63,8 +433,9 @@ pub mod pipeline {
             let mut has_errors = false;
 
             for agg in op.aggregate_functions {
+                let name = agg.1.value.name().to_string();
                 if let Ok(operator_function) = agg.1.semantic_analysis(pipeline) {
-                    agg_functions.push((agg.0, operator_function));
+                    agg_functions.push((agg.0, name, operator_function));
                 } else {
                     has_errors = true;
                 }
@@ -93,193 +464,1072 @@ pub mod pipeline {
             }
         }
 
-        pub fn new(pipeline: &QueryContainer) -> Result<Self, Error> {
+        /// Whether the rows of an aggregate should be sorted before the operator that follows
+        /// it, so that a limit or chart sees the largest rows first.
+        fn needs_implicit_sort(next: Option<&Operator>) -> bool {
+            match next {
+                Some(Operator::Inline(Positioned {
+                    value: InlineOperator::Limit { .. },
+                    ..
+                })) => true,
+                Some(Operator::Chart(_)) => true,
+                None => true,
+                _ => false,
+            }
+        }
+
+        /// Parse and check a query, returning the errors rather than reporting them.  The
+        /// resulting pipeline renders to stdout as a table if `process` is used.
+        pub fn compile(query: &str) -> Result<Self, Vec<QueryError>> {
+            let container = QueryContainer::new(query.to_string(), Box::new(SilentReporter));
+            Pipeline::new(&container, OutputFormat::Table).map_err(|e| errors_or(&container, e))
+        }
+
+        pub fn new(pipeline: &QueryContainer, output: OutputFormat) -> Result<Self, Error> {
             let parsed = pipeline.parse().map_err(|_pos| CompileError::Parse);
             let query = parsed?;
-            let filters = query.search.iter().map(Keyword::to_regex).collect();
+            let schema::Check {
+                unknown,
+                unresolved,
+                operators: op_columns,
+            } = schema::check(&query);
+            let uses_file_column = op_columns
+                .iter()
+                .any(|columns| columns.inputs.iter().any(|input| input == FILE_COLUMN));
+            let mut op_columns = op_columns.into_iter();
+            let mut steps = Vec::new();
+            let filters = query.search.iter().map(Keyword::to_bytes_regex).collect();
+            let highlight = Keyword::any_regex(&query.search);
             let mut in_agg = false;
-            let mut pre_agg: Vec<Box<operator::UnaryPreAggOperator>> = Vec::new();
+            // The key columns of the last aggregate, for `chart`.
+            let mut keys = Vec::new();
+            let mut pre_agg: Vec<(String, Box<operator::OperatorBuilder + Send + Sync>)> =
+                Vec::new();
             let mut post_agg: Vec<Box<operator::AggregateOperator>> = Vec::new();
             let mut op_iter = query.operators.into_iter().peekable();
             let mut has_errors = false;
             while let Some(op) = op_iter.next() {
+                // The check describes every operator in the query.
+                let columns = op_columns.next().unwrap();
                 match op {
                     Operator::Inline(inline_op) => {
+                        let name = inline_op.value.name().to_string();
+                        let note = match inline_op.value {
+                            InlineOperator::Limit { count: None, .. } => {
+                                Some(format!("default count of {}", DEFAULT_LIMIT))
+                            }
+                            _ if in_agg => Some("applied to each row of the aggregate".to_string()),
+                            _ => None,
+                        };
+                        steps.push(explain::Step {
+                            description: explain::text(pipeline.query(), &inline_op),
+                            note,
+                            columns,
+                        });
                         let op_builder = inline_op.semantic_analysis(pipeline)?;
 
                         if !in_agg {
-                            pre_agg.push(op_builder.build());
+                            pre_agg.push((name, op_builder));
                         } else {
-                            post_agg.push(Box::new(operator::PreAggAdapter::new(op_builder)));
+                            post_agg
+                                .push(Box::new(operator::PreAggAdapter::new(&name, op_builder)));
                         }
                     }
                     Operator::MultiAggregate(agg_op) => {
                         in_agg = true;
+                        keys = agg_op.key_col_headers.clone();
                         let sorter = Pipeline::implicit_sort(&agg_op);
+                        let functions: Vec<String> = agg_op
+                            .aggregate_functions
+                            .iter()
+                            .map(|(_, function)| explain::text(pipeline.query(), function))
+                            .collect();
+                        let mut description = functions.join(", ");
+                        if !agg_op.key_col_headers.is_empty() {
+                            description =
+                                format!("{} by {}", description, agg_op.key_col_headers.join(", "));
+                        }
+                        let sort_step = explain::Step {
+                            description: explain::sort(&sorter),
+                            note: Some("implicit".to_string()),
+                            columns: schema::Columns {
+                                inputs: sorter.sort_cols.clone(),
+                                ..columns.clone()
+                            },
+                        };
+                        steps.push(explain::Step {
+                            description,
+                            note: None,
+                            columns,
+                        });
                         if let Ok(op) = Pipeline::convert_multi_agg(agg_op, pipeline) {
                             post_agg.push(op);
 
-                            let needs_sort = match op_iter.peek() {
-                                Some(Operator::Inline(Positioned {
-                                    value: InlineOperator::Limit { .. },
-                                    ..
-                                })) => true,
-                                None => true,
-                                _ => false,
-                            };
-                            if needs_sort {
+                            if Pipeline::needs_implicit_sort(op_iter.peek()) {
+                                steps.push(sort_step);
                                 post_agg.push(Pipeline::convert_sort(sorter));
                             }
                         } else {
                             has_errors = true;
                         }
                     }
-                    Operator::Sort(sort_op) => post_agg.push(Pipeline::convert_sort(sort_op)),
+                    Operator::LogReduce(logreduce_op) => {
+                        steps.push(explain::Step {
+                            description: match logreduce_op.value.input_column {
+                                Some(ref column) => format!("logreduce from {}", column),
+                                None => "logreduce".to_string(),
+                            },
+                            note: None,
+                            columns: columns.clone(),
+                        });
+                        post_agg.push(Box::new(logreduce_op.semantic_analysis(in_agg, pipeline)?));
+                        in_agg = true;
+                        keys = vec!["_template".to_string()];
+                        if Pipeline::needs_implicit_sort(op_iter.peek()) {
+                            let sorter = SortOperator {
+                                sort_cols: vec!["_count".to_string()],
+                                direction: SortMode::Descending,
+                            };
+                            steps.push(explain::Step {
+                                description: explain::sort(&sorter),
+                                note: Some("implicit".to_string()),
+                                columns: schema::Columns {
+                                    inputs: sorter.sort_cols.clone(),
+                                    ..columns
+                                },
+                            });
+                            post_agg.push(Pipeline::convert_sort(sorter));
+                        }
+                    }
+                    Operator::Sort(sort_op) => {
+                        steps.push(explain::Step {
+                            description: explain::sort(&sort_op),
+                            note: None,
+                            columns,
+                        });
+                        post_agg.push(Pipeline::convert_sort(sort_op))
+                    }
+                    Operator::Chart(chart_op) => {
+                        steps.push(explain::Step {
+                            description: match chart_op.column {
+                                Some(ref column) => format!("chart {}", column),
+                                None => "chart".to_string(),
+                            },
+                            note: None,
+                            columns,
+                        });
+                        post_agg.push(Box::new(operator::Chart::new(
+                            chart_op.column,
+                            output == OutputFormat::Table && atty::is(atty::Stream::Stdout),
+                            keys.clone(),
+                        )))
+                    }
+                    Operator::Macro(name) => {
+                        pipeline
+                            .report_error_for(format!("No macro named `{}`", name.value))
+                            .with_code_pointer(&name, "")
+                            .with_resolution(
+                                "Macros are expanded when the query is read with a config",
+                            )
+                            .send_report();
+                        has_errors = true;
+                    }
                 }
             }
             if has_errors {
                 return Err(CompileError::Parse.into());
             }
+            for unknown in &unknown {
+                unknown.report(pipeline);
+            }
             Result::Ok(Pipeline {
                 filter: filters,
+                search: query.search,
+                highlight,
                 pre_aggregates: pre_agg,
                 aggregators: post_agg,
-                renderer: Renderer::new(
-                    RenderConfig {
-                        floating_points: 2,
-                        min_buffer: 4,
-                        max_buffer: 8,
-                    },
-                    Duration::from_millis(50),
-                ),
+                output,
+                num_threads: num_cpus::get(),
+                interactive: false,
+                error_mode: ErrorMode::Print,
+                unresolved_columns: unresolved,
+                uses_file_column,
+                steps,
             })
         }
 
-        fn render_noagg(mut renderer: Renderer, rx: &Receiver<Row>) {
-            loop {
-                let next = rx.recv_timeout(Duration::from_millis(50));
-                match next {
-                    Ok(row) => {
-                        let result = renderer.render(&row, false);
+        /// Describe the compiled pipeline: the filters, and the operators in the order they run,
+        /// where they run and the columns they use and produce.  This includes operators that
+        /// aren't in the query, like the sort that's added after an aggregate.
+        pub fn explain(&self) -> String {
+            let (pre_agg, aggregates) = self.steps.split_at(self.pre_aggregates.len());
+            let num_stateless = self
+                .pre_aggregates
+                .iter()
+                .take_while(|(_, op)| op.is_stateless())
+                .count();
+            let (stateless, ordered) = pre_agg.split_at(num_stateless);
+            let split = ordered.is_empty()
+                && self
+                    .aggregators
+                    .first()
+                    .is_some_and(|head| head.split().is_some());
+            explain::render(
+                &self.search,
+                &[
+                    (
+                        match self.num_threads {
+                            1 => "Before the first aggregate, on a worker thread".to_string(),
+                            n => format!("Before the first aggregate, on {} worker threads", n),
+                        },
+                        stateless,
+                    ),
+                    (
+                        "Before the first aggregate, in order on one thread".to_string(),
+                        ordered,
+                    ),
+                    (
+                        if split {
+                            "Aggregates, on one thread after the workers aggregate their part of \
+                             the input"
+                        } else {
+                            "Aggregates, on one thread"
+                        }
+                        .to_string(),
+                        aggregates,
+                    ),
+                ],
+            )
+        }
+
+        /// Choose what happens when an operator fails on a record.  Errors are printed as they
+        /// happen by default.
+        pub fn error_mode(mut self, error_mode: ErrorMode) -> Self {
+            self.error_mode = error_mode;
+            self
+        }
+
+        /// Show the results of a query with aggregate operators in a full-screen view that can be
+        /// scrolled, sorted and paused, if stdout is a terminal.  Other queries are rendered as
+        /// usual.
+        pub fn interactive(mut self, interactive: bool) -> Self {
+            self.interactive = interactive;
+            self
+        }
 
-                        if let Err(e) = result {
-                            eprintln!("error: {}", e);
+        /// Process the input stream, rendering the results to stdout.  Fails if an operator
+        /// fails in strict mode.
+        pub fn process<T: BufRead>(self, buf: T) -> Result<(), Error> {
+            self.process_input(Input::Stream(buf))
+        }
+
+        /// Process a single input, rendering the results to stdout.
+        pub fn process_input<T: BufRead>(self, input: Input<T>) -> Result<(), Error> {
+            self.render_inputs(iter::once((None, input)))
+        }
+
+        /// Process a series of named inputs, in order, as if they were a single stream.  Each
+        /// record has the name of its input in the `_file` column.
+        pub fn process_files<T: BufRead, I: IntoIterator<Item = (String, Input<T>)>>(
+            self,
+            files: I,
+        ) -> Result<(), Error> {
+            self.render_inputs(files.into_iter().map(|(name, input)| (Some(name), input)))
+        }
+
+        /// Process the input stream, passing the results to `sink` instead of rendering them.
+        /// Nothing is printed: the sink is handed back once the input has been processed, along
+        /// with the warnings and the errors that operators ran into.  Fails if the input can't
+        /// be read, the sink fails or an operator fails in strict mode.
+        pub fn process_to<T: BufRead, S: RowSink + 'static>(
+            self,
+            buf: T,
+            sink: S,
+        ) -> Result<(S, Diagnostics), Error> {
+            let stats = Arc::new(self.stats(true));
+            let (sink, result) =
+                self.process_inputs(iter::once((None, Input::Stream(buf))), sink, stats.clone());
+            result?;
+            let diagnostics = Diagnostics {
+                warnings: mem::take(&mut *stats.warnings.lock().unwrap()),
+                errors: mem::take(&mut *stats.errors.lock().unwrap()),
+            };
+            Ok((sink, diagnostics))
+        }
+
+        /// Run the query over the lines and return the results, along with the warnings and
+        /// errors.  Fails if an operator fails in strict mode.
+        pub fn run<S: AsRef<str>, I: IntoIterator<Item = S>>(
+            self,
+            lines: I,
+        ) -> Result<QueryResult, Error> {
+            let has_aggregates = !self.aggregators.is_empty();
+            let reader = LineReader {
+                lines: lines.into_iter(),
+                line: Vec::new(),
+                pos: 0,
+            };
+            let (sink, diagnostics) =
+                self.process_to(BufReader::new(reader), ResultSink::default())?;
+            let output = match sink.aggregate {
+                Some(aggregate) if has_aggregates => QueryOutput::Aggregate(aggregate),
+                _ => QueryOutput::Records(sink.records),
+            };
+            Ok(QueryResult {
+                output,
+                diagnostics,
+            })
+        }
+
+        /// Render the results to stdout, followed by a summary of any errors on stderr.
+        fn render_inputs<T, I>(self, inputs: I) -> Result<(), Error>
+        where
+            T: BufRead,
+            I: Iterator<Item = (Option<String>, Input<T>)>,
+        {
+            let interactive =
+                self.interactive && !self.aggregators.is_empty() && atty::is(atty::Stream::Stdout);
+            // Errors would be drawn over the interactive view, so only the summary is printed.
+            let stats = Arc::new(self.stats(interactive));
+            let sink: Box<RowSink> = if interactive {
+                Box::new(Interactive::new(stats.clone()))
+            } else {
+                self.renderer()
+            };
+            let (_, result) = self.process_inputs(inputs, sink, stats.clone());
+            match result {
+                // Closing the interactive view stops processing early, which isn't an error.
+                Err(ref e) if e.downcast_ref::<tui::Closed>().is_some() => (),
+                result => result?,
+            }
+            stats.print_summary();
+            Ok(())
+        }
+
+        fn stats(&self, quiet: bool) -> Stats {
+            Stats {
+                print_errors: self.error_mode == ErrorMode::Print && !quiet,
+                strict: self.error_mode == ErrorMode::Strict,
+                columns: ColumnCheck::new(self.unresolved_columns.clone()),
+                print_warnings: !quiet,
+                ..Stats::default()
+            }
+        }
+
+        fn renderer(&self) -> Box<RowSink> {
+            let renderer = Renderer::new(
+                RenderConfig {
+                    floating_points: 2,
+                    min_buffer: 4,
+                    max_buffer: 8,
+                },
+                Duration::from_millis(50),
+                self.output,
+                self.highlight.clone(),
+            );
+            if self.uses_file_column {
+                Box::new(renderer.show_file_column())
+            } else {
+                Box::new(renderer)
+            }
+        }
+
+        /// Process the inputs on the worker threads, passing the results to the sink.  Returns
+        /// the sink along with the error that processing stopped at, if any.
+        fn process_inputs<T, I, S>(
+            self,
+            inputs: I,
+            mut sink: S,
+            stats: Arc<Stats>,
+        ) -> (S, Result<(), Error>)
+        where
+            T: BufRead,
+            I: Iterator<Item = (Option<String>, Input<T>)>,
+            S: RowSink + 'static,
+        {
+            let (chunk_tx, chunk_rx) = bounded(self.num_threads * 4);
+            let (batch_tx, batch_rx) = bounded(self.num_threads * 4);
+
+            // Stateless operators run on the worker threads, everything from the first stateful
+            // operator on has to run on a single thread and see the records in order.
+            let mut pre_aggregates = self.pre_aggregates;
+            let num_stateless = pre_aggregates
+                .iter()
+                .position(|(_, op)| !op.is_stateless())
+                .unwrap_or(pre_aggregates.len());
+            let ordered: Vec<_> = pre_aggregates
+                .drain(num_stateless..)
+                .map(|(name, op)| (name, op.build()))
+                .collect();
+            let aggregators = self.aggregators;
+            // If every record goes straight into the first aggregate operator, the workers can
+            // aggregate their chunks and have the results merged instead.
+            let template = if ordered.is_empty() {
+                aggregators
+                    .first()
+                    .and_then(|head| head.split())
+                    .map(Arc::new)
+            } else {
+                None
+            };
+
+            let filter = Arc::new(self.filter);
+            let stateless = Arc::new(pre_aggregates);
+            let workers: Vec<_> = (0..self.num_threads)
+                .map(|_| {
+                    let filter = filter.clone();
+                    let stateless = stateless.clone();
+                    let template = template.clone();
+                    let stats = stats.clone();
+                    let chunk_rx = chunk_rx.clone();
+                    let batch_tx = batch_tx.clone();
+                    thread::spawn(move || {
+                        Pipeline::process_chunks(
+                            &filter,
+                            &stateless,
+                            template.as_ref().map(|t| &***t),
+                            &stats,
+                            &chunk_rx,
+                            &batch_tx,
+                        )
+                    })
+                })
+                .collect();
+            drop(chunk_rx);
+            drop(batch_tx);
+
+            let t = thread::spawn(move || {
+                let result =
+                    Pipeline::process_batches(ordered, aggregators, &stats, &mut sink, &batch_rx);
+                (sink, result)
+            });
+
+            let mut seq = 0;
+            let mut read_error = None;
+            for (name, input) in inputs {
+                let mut send = |data| {
+                    let chunk = Chunk {
+                        seq,
+                        name: name.clone(),
+                        data,
+                    };
+                    seq += 1;
+                    chunk_tx.send(chunk).is_ok()
+                };
+                let result = match input {
+                    Input::Stream(buf) => {
+                        Pipeline::read_chunks(buf, |data| send(ChunkData::Owned(data)))
+                    }
+                    Input::Mapped(map) => {
+                        Ok(Pipeline::map_chunks(Arc::new(map), MAPPED_CHUNK_SIZE, send))
+                    }
+                    Input::Loaded(data) => Ok(Pipeline::map_chunks(data, MAPPED_CHUNK_SIZE, send)),
+                };
+                match result {
+                    Ok(true) => (),
+                    // The output was closed, so there's no point in reading the rest.
+                    Ok(false) => break,
+                    // A file that can't be read is skipped, but without names there's only
+                    // the one input.
+                    Err(e) => match name {
+                        Some(ref name) => eprintln!("error: {}: {}", name, e),
+                        None => {
+                            read_error = Some(e);
                             break;
                         }
+                    },
+                }
+            }
+
+            // Dropping the senders causes the threads to exit once they've processed everything.
+            drop(chunk_tx);
+            let mut worker_error = None;
+            for worker in workers {
+                if let Err(panic) = worker.join() {
+                    // The panic has already been printed, only its message is kept.
+                    let message = match panic.downcast_ref::<&str>() {
+                        Some(message) => message.to_string(),
+                        None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+                    };
+                    worker_error = Some(ProcessError::Worker { message });
+                }
+            }
+            // The sink can't be handed back if the thread panicked, so pass the panic on.
+            let (sink, result) = t.join().unwrap_or_else(|e| panic::resume_unwind(e));
+            match (worker_error, read_error) {
+                (Some(e), _) => (sink, Err(e.into())),
+                (None, Some(e)) => (sink, Err(e.into())),
+                (None, None) => (sink, result),
+            }
+        }
+
+        /// Split the input into chunks of complete lines and pass them to `send`.  Each chunk is
+        /// whatever can be read without blocking, so a slow stream is still processed line by
+        /// line.  Returns false if `send` asked to stop reading.
+        fn read_chunks<T: BufRead, F: FnMut(Vec<u8>) -> bool>(
+            mut buf: T,
+            mut send: F,
+        ) -> Result<bool, std::io::Error> {
+            // The start of a line that hasn't been terminated yet.
+            let mut partial_line = Vec::new();
+            loop {
+                let (chunk, consumed) = {
+                    let available = buf.fill_buf()?;
+                    if available.is_empty() {
+                        break;
                     }
-                    Err(RecvTimeoutError::Timeout) => {}
-                    Err(RecvTimeoutError::Disconnected) => break,
+                    match memrchr(b'\n', available) {
+                        Some(pos) => {
+                            let mut chunk = mem::take(&mut partial_line);
+                            chunk.extend_from_slice(&available[..=pos]);
+                            (Some(chunk), pos + 1)
+                        }
+                        None => {
+                            partial_line.extend_from_slice(available);
+                            (None, available.len())
+                        }
+                    }
+                };
+                buf.consume(consumed);
+                if let Some(chunk) = chunk {
+                    if !send(chunk) {
+                        return Ok(false);
+                    }
+                }
+            }
+            if partial_line.is_empty() {
+                Ok(true)
+            } else {
+                Ok(send(partial_line))
+            }
+        }
+
+        /// Split an input that is in memory into chunks of roughly `chunk_size`, each extended to
+        /// the end of its last line, and pass them to `send`.  Returns false if `send` asked to
+        /// stop.
+        fn map_chunks<F: FnMut(ChunkData) -> bool>(
+            data: Arc<AsRef<[u8]> + Send + Sync>,
+            chunk_size: usize,
+            mut send: F,
+        ) -> bool {
+            let bytes = (*data).as_ref();
+            let mut start = 0;
+            while start < bytes.len() {
+                let end = (start + chunk_size).min(bytes.len());
+                let end = memchr(b'\n', &bytes[end - 1..]).map_or(bytes.len(), |i| end + i);
+                if !send(ChunkData::Mapped(data.clone(), start..end)) {
+                    return false;
                 }
+                start = end;
             }
+            true
         }
 
-        fn render_aggregate(
-            mut head: Box<operator::AggregateOperator>,
-            mut rest: Vec<Box<operator::AggregateOperator>>,
-            mut renderer: Renderer,
-            rx: &Receiver<Row>,
+        /// Runs on each of the worker threads: filter the lines in each chunk and run them through
+        /// the stateless operators.  When aggregating, the partial aggregate is sent every
+        /// `PARTIAL_INTERVAL` or when the input stalls, since merging isn't free.
+        fn process_chunks(
+            filter: &[regex::bytes::Regex],
+            stateless: &[(String, Box<operator::OperatorBuilder + Send + Sync>)],
+            template: Option<&operator::AggregateOperator>,
+            stats: &Stats,
+            rx: &Receiver<Chunk>,
+            tx: &Sender<Batch>,
         ) {
+            let mut preaggs: Vec<_> = stateless
+                .iter()
+                .map(|(name, op)| (name.clone(), op.build()))
+                .collect();
+            let mut partial: Option<Box<operator::AggregateOperator>> = None;
+            let mut last_partial = Instant::now();
             loop {
-                let next = rx.recv_timeout(Duration::from_millis(50));
-                match next {
-                    Ok(row) => (*head).process(row),
-                    Err(RecvTimeoutError::Timeout) => {}
+                if stats.failed() {
+                    return;
+                }
+                let stalled = match rx.recv_timeout(PARTIAL_INTERVAL) {
+                    Ok(chunk) => {
+                        let mut errors = operator::ErrorCounts::default();
+                        let records = Pipeline::chunk_records(
+                            &chunk,
+                            filter,
+                            stats,
+                            &mut errors,
+                            &mut preaggs,
+                        );
+                        stats.add_errors(&errors);
+                        if stats.failed() {
+                            return;
+                        }
+                        match template {
+                            Some(template) => {
+                                let agg = partial.get_or_insert_with(|| template.split().unwrap());
+                                agg.start_chunk(chunk.seq);
+                                for rec in records {
+                                    stats.check_columns(&rec);
+                                    agg.process(Row::Record(rec));
+                                }
+                            }
+                            None => {
+                                if tx.send(Batch::Records(chunk.seq, records)).is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                        false
+                    }
+                    Err(RecvTimeoutError::Timeout) => true,
                     Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if stalled || last_partial.elapsed() > PARTIAL_INTERVAL {
+                    last_partial = Instant::now();
+                    if let Some(agg) = partial.take() {
+                        if tx.send(Batch::Partial(agg)).is_err() {
+                            return;
+                        }
+                    }
                 }
+            }
+            if let Some(agg) = partial {
+                let _ = tx.send(Batch::Partial(agg));
+            }
+        }
 
-                if renderer.should_print() {
-                    let result =
-                        renderer.render(&Pipeline::run_agg_pipeline(&head, &mut rest), false);
-
-                    if let Err(e) = result {
-                        eprintln!("error: {}", e);
-                        return;
-                    }
+        /// Turn the lines in a chunk that match the filter into records.
+        fn chunk_records(
+            chunk: &Chunk,
+            filter: &[regex::bytes::Regex],
+            stats: &Stats,
+            errors: &mut operator::ErrorCounts,
+            preaggs: &mut [(String, Box<operator::UnaryPreAggOperator>)],
+        ) -> Vec<Record> {
+            let data = chunk.data.as_slice();
+            // Every line ends with a newline, except perhaps the last line of the input.
+            let unterminated = !data.is_empty() && !data.ends_with(b"\n");
+            let lines = memchr_iter(b'\n', data).count() + unterminated as usize;
+            stats.lines_read.fetch_add(lines, Ordering::Relaxed);
+            let data = data.strip_suffix(b"\n").unwrap_or(data);
+            let lines = Pipeline::matching_lines(data, filter);
+            stats
+                .lines_matched
+                .fetch_add(lines.len(), Ordering::Relaxed);
+            let mut records = Vec::with_capacity(lines.len());
+            for line in lines {
+                let mut rec = Record::new(&String::from_utf8_lossy(line));
+                if let Some(ref name) = chunk.name {
+                    rec = rec.put(FILE_COLUMN, Value::Str(name.clone()));
                 }
+                Pipeline::proc_preagg(rec, preaggs, stats, errors, &mut |rec| records.push(rec));
             }
-            let result = renderer.render(&Pipeline::run_agg_pipeline(&head, &mut rest), true);
+            records
+        }
 
-            if let Err(e) = result {
-                eprintln!("error: {}", e);
-                return;
+        /// Find the lines in the buffer that match every filter.  Rather than splitting the
+        /// whole buffer into lines, search it for the first filter and only check the lines it
+        /// turns up against the rest.
+        fn matching_lines<'a>(data: &'a [u8], filter: &[regex::bytes::Regex]) -> Vec<&'a [u8]> {
+            let (first, rest) = match filter.split_first() {
+                Some(split) => split,
+                None => return data.split(|&b| b == b'\n').collect(),
+            };
+            let mut lines = Vec::new();
+            let mut pos = 0;
+            while pos <= data.len() {
+                let found = match first.find_at(data, pos) {
+                    Some(found) => found,
+                    None => break,
+                };
+                let start = memrchr(b'\n', &data[..found.start()]).map_or(0, |i| i + 1);
+                let end =
+                    memchr(b'\n', &data[found.end()..]).map_or(data.len(), |i| found.end() + i);
+                let line = &data[start..end];
+                if rest.iter().all(|re| re.is_match(line)) {
+                    lines.push(line);
+                }
+                pos = end + 1;
             }
+            lines
         }
 
-        pub fn process<T: BufRead>(self, mut buf: T) {
-            let (tx, rx) = bounded(1000);
-            let mut aggregators = self.aggregators;
-            let mut preaggs = self.pre_aggregates;
-            let renderer = self.renderer;
-            let t = if !aggregators.is_empty() {
-                let head = aggregators.remove(0);
-                thread::spawn(move || Pipeline::render_aggregate(head, aggregators, renderer, &rx))
+        /// Runs on a single thread: put the batches from the workers back in order, run them
+        /// through the stateful operators and the aggregates, and render the results.
+        fn process_batches<S: RowSink>(
+            mut ordered: Vec<(String, Box<operator::UnaryPreAggOperator>)>,
+            mut aggregators: Vec<Box<operator::AggregateOperator>>,
+            stats: &Stats,
+            sink: &mut S,
+            rx: &Receiver<Batch>,
+        ) -> Result<(), Error> {
+            let mut head = if aggregators.is_empty() {
+                None
             } else {
-                thread::spawn(move || Pipeline::render_noagg(renderer, &rx))
+                Some(aggregators.remove(0))
             };
+            let mut pending = HashMap::new();
+            let mut next_seq = 0;
+            let mut errors = operator::ErrorCounts::default();
+            loop {
+                match rx.recv_timeout(Duration::from_millis(50)) {
+                    Ok(Batch::Records(seq, records)) => {
+                        pending.insert(seq, records);
+                        while let Some(records) = pending.remove(&next_seq) {
+                            next_seq += 1;
+                            for rec in records {
+                                Pipeline::proc_ordered(
+                                    rec,
+                                    &mut ordered,
+                                    &mut head,
+                                    stats,
+                                    &mut errors,
+                                    sink,
+                                )?;
+                                if stats.strict && !errors.is_empty() {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    Ok(Batch::Partial(partial)) => {
+                        if let Some(ref mut head) = head {
+                            head.merge(&*partial);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
 
-            // This is pretty slow in practice. We could move line splitting until after
-            // we find a match. Another option is moving the transformation to String until
-            // after we match (staying as Vec<u8> until then)
-            let mut line = String::with_capacity(1024);
-            while buf.read_line(&mut line).unwrap() > 0 {
-                if self.filter.iter().all(|re| re.is_match(&line)) {
-                    if !Pipeline::proc_preagg(Record::new(&line), &mut preaggs, &tx) {
-                        break;
+                if let Some(ref mut head) = head {
+                    errors.merge(&head.take_errors());
+                }
+                stats.add_errors(&errors);
+                errors = operator::ErrorCounts::default();
+                if stats.failed() {
+                    return Err(stats.strict_error().into());
+                }
+
+                if let Some(ref head) = head {
+                    if sink.wants_snapshot() {
+                        sink.aggregate(Pipeline::run_agg_pipeline(head, &mut aggregators), false)?;
                     }
                 }
-                line.clear();
             }
 
             // Drain any remaining records from the operators.
-            while !preaggs.is_empty() {
-                let preagg = preaggs.remove(0);
+            while !ordered.is_empty() {
+                let (_, preagg) = ordered.remove(0);
 
                 for rec in preagg.drain() {
-                    if !Pipeline::proc_preagg(rec, &mut preaggs, &tx) {
+                    Pipeline::proc_ordered(rec, &mut ordered, &mut head, stats, &mut errors, sink)?;
+                    if stats.strict && !errors.is_empty() {
                         break;
                     }
                 }
             }
+            if let Some(ref mut head) = head {
+                errors.merge(&head.take_errors());
+            }
+            stats.add_errors(&errors);
+            if stats.failed() {
+                return Err(stats.strict_error().into());
+            }
+            stats.warn(stats.columns.finish());
 
-            // Drop tx when causes the thread to exit.
-            drop(tx);
-            match t.join() {
-                Ok(_) => (),
-                Err(e) => println!("Error: {:?}", e),
+            if let Some(ref head) = head {
+                let aggregate = Pipeline::run_agg_pipeline(head, &mut aggregators);
+                // The operators after the first aggregate go over the whole aggregate each time
+                // it's run, so only the errors from the final run are counted.
+                let mut errors = operator::ErrorCounts::default();
+                for agg in &mut aggregators {
+                    errors.merge(&agg.take_errors());
+                }
+                stats.add_errors(&errors);
+                if stats.failed() {
+                    return Err(stats.strict_error().into());
+                }
+                sink.aggregate(aggregate, true)?;
             }
+            Ok(())
         }
 
-        /// Process a record using the pre-agg operators.  The output of the last operator will be
-        /// sent to `tx`.
-        fn proc_preagg(
-            mut rec: Record,
-            pre_aggs: &mut [Box<operator::UnaryPreAggOperator>],
-            tx: &Sender<Row>,
-        ) -> bool {
-            for pre_agg in pre_aggs {
-                match (*pre_agg).process_mut(rec) {
-                    Ok(Some(next_rec)) => rec = next_rec,
-                    Ok(None) => return true,
-                    Err(err) => {
-                        eprintln!("error: {}", err);
-                        return true;
-                    }
+        /// Pass a record that made it through the pre-agg operators to the aggregates, or to the
+        /// sink if there aren't any.
+        fn output<S: RowSink>(
+            rec: Record,
+            head: &mut Option<Box<operator::AggregateOperator>>,
+            stats: &Stats,
+            sink: &mut S,
+        ) -> Result<(), Error> {
+            stats.check_columns(&rec);
+            match head {
+                Some(head) => {
+                    head.process(Row::Record(rec));
+                    Ok(())
                 }
+                None => sink.record(rec),
             }
+        }
 
-            tx.send(Row::Record(rec)).is_ok()
+        /// Process a record using the stateful pre-agg operators, and pass the records that come
+        /// out of them to the aggregates or the sink.
+        fn proc_ordered<S: RowSink>(
+            rec: Record,
+            ordered: &mut [(String, Box<operator::UnaryPreAggOperator>)],
+            head: &mut Option<Box<operator::AggregateOperator>>,
+            stats: &Stats,
+            errors: &mut operator::ErrorCounts,
+            sink: &mut S,
+        ) -> Result<(), Error> {
+            let mut result = Ok(());
+            Pipeline::proc_preagg(rec, ordered, stats, errors, &mut |rec| {
+                if result.is_ok() {
+                    result = Pipeline::output(rec, head, stats, sink);
+                }
+            });
+            result
+        }
+
+        /// Process a record using the pre-agg operators.  Each record that comes out of the last
+        /// operator is passed to `out`.
+        fn proc_preagg(
+            rec: Record,
+            pre_aggs: &mut [(String, Box<operator::UnaryPreAggOperator>)],
+            stats: &Stats,
+            errors: &mut operator::ErrorCounts,
+            out: &mut FnMut(Record),
+        ) {
+            let ((name, pre_agg), rest) = match pre_aggs.split_first_mut() {
+                Some(split) => split,
+                None => return out(rec),
+            };
+            let result = pre_agg.process_mut(rec, &mut |rec| {
+                // An operator like split can emit many records for one, so strict mode checks
+                // for errors after each of them rather than only once the record is done.
+                if stats.strict && !errors.is_empty() {
+                    return;
+                }
+                stats.observe_columns(&rec);
+                Pipeline::proc_preagg(rec, rest, stats, errors, out)
+            });
+            if let Err(err) = result {
+                if stats.print_errors {
+                    eprintln!("error: {}", err);
+                }
+                errors.add(name, &err);
+            }
         }
 
         pub fn run_agg_pipeline(
             head: &Box<operator::AggregateOperator>,
             rest: &mut [Box<operator::AggregateOperator>],
-        ) -> Row {
-            let mut row = Row::Aggregate((*head).emit());
+        ) -> Aggregate {
+            let mut aggregate = (*head).emit();
             for agg in (*rest).iter_mut() {
-                (*agg).process(row);
-                row = Row::Aggregate((*agg).emit());
+                (*agg).process(Row::Aggregate(aggregate));
+                aggregate = (*agg).emit();
+            }
+            aggregate
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use flate2::read::MultiGzDecoder;
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use memmap::MmapMut;
+        use std::io::Write;
+
+        /// Lines with a key, a number and their position in the input.
+        fn input() -> String {
+            (0..5000)
+                .map(|i| format!("k{} {} {}\n", i % 7, (i * 31) % 101, i))
+                .collect()
+        }
+
+        /// Run a query with the given number of worker threads, reading the input in chunks of
+        /// `chunk_size` bytes.
+        fn run(query: &str, num_threads: usize, chunk_size: usize) -> ResultSink {
+            let container = QueryContainer::new(query.to_string(), Box::new(SilentReporter));
+            let mut pipeline = Pipeline::new(&container, OutputFormat::Json).unwrap();
+            pipeline.num_threads = num_threads;
+            let input = input();
+            let reader = BufReader::with_capacity(chunk_size, input.as_bytes());
+            let (sink, diagnostics) = pipeline.process_to(reader, ResultSink::default()).unwrap();
+            assert!(diagnostics.errors.is_empty());
+            sink
+        }
+
+        #[test]
+        fn parallel_matches_sequential() {
+            let queries = &[
+                r#"* | parse "* * *" as k, v, i | count, sum(v), average(v) by k | sort by k"#,
+                r#"* | parse "* * *" as k, v, i | count_distinct(v), p50(v) by k | sort by k"#,
+                r#"* | parse "* * *" as k, v, i | where v > 50 | count by k | sort by k"#,
+                r#"* | parse "* * *" as k, v, i | count by k | where _count > 714 | count"#,
+                // The example of each template is the first line with it, whichever thread saw it.
+                "* | logreduce",
+            ];
+            for query in queries {
+                let sequential = run(query, 1, 1024 * 1024);
+                let parallel = run(query, 4, 256);
+                assert_eq!(parallel.aggregate, sequential.aggregate, "{}", query);
+                assert!(parallel.aggregate.is_some());
+            }
+        }
+
+        #[test]
+        fn ordered_operators_see_input_order() {
+            let query = r#"* | parse "* * *" as k, v, i | total(v) | limit 3000"#;
+            let sequential = run(query, 1, 1024 * 1024);
+            let parallel = run(query, 4, 256);
+            assert_eq!(parallel.records.len(), 3000);
+            let positions: Vec<&Value> =
+                parallel.records.iter().map(|rec| &rec.data["i"]).collect();
+            let expected: Vec<Value> = (0..3000).map(Value::Int).collect();
+            assert_eq!(positions, expected.iter().collect::<Vec<_>>());
+            assert_eq!(parallel.records, sequential.records);
+        }
+
+        fn filter(keywords: &[&str]) -> Vec<regex::bytes::Regex> {
+            keywords
+                .iter()
+                .map(|keyword| Keyword::new_wildcard(keyword.to_string()).to_bytes_regex())
+                .collect()
+        }
+
+        #[test]
+        fn matching_lines() {
+            // No trailing newline, as at the end of the input.
+            let data = b"error at start\ninfo ok\nERROR db timeout\nwarn db db\nerror db at end";
+            let lines = |keywords: &[&str]| Pipeline::matching_lines(data, &filter(keywords));
+            assert_eq!(lines(&[]).len(), 5);
+            assert_eq!(
+                lines(&["error"]),
+                vec![
+                    &b"error at start"[..],
+                    b"ERROR db timeout",
+                    b"error db at end"
+                ]
+            );
+            // Every keyword has to be in the line, in any case.
+            assert_eq!(
+                lines(&["db", "Error"]),
+                vec![&b"ERROR db timeout"[..], b"error db at end"]
+            );
+            // A keyword that is in a line twice only finds it once.
+            assert_eq!(lines(&["warn", "db"]), vec![&b"warn db db"[..]]);
+            assert_eq!(lines(&["start"]), vec![&b"error at start"[..]]);
+            assert_eq!(lines(&["end"]), vec![&b"error db at end"[..]]);
+            assert!(lines(&["missing"]).is_empty());
+        }
+
+        /// Input with a last line that isn't terminated.
+        fn unterminated_input() -> String {
+            input() + "k0 1 5000"
+        }
+
+        /// Check that the chunks are made up of whole lines, which are the lines of the input.
+        fn assert_lines(chunks: &[Vec<u8>], input: &str) {
+            assert!(chunks.len() > 1);
+            let (last, rest) = chunks.split_last().unwrap();
+            assert!(rest.iter().all(|chunk| chunk.ends_with(b"\n")));
+            assert!(!last.ends_with(b"\n"));
+            let lines: Vec<&[u8]> = chunks
+                .iter()
+                .flat_map(|chunk| {
+                    let chunk = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+                    chunk.split(|&b| b == b'\n')
+                })
+                .collect();
+            let expected: Vec<&[u8]> = input.lines().map(str::as_bytes).collect();
+            assert_eq!(lines, expected);
+        }
+
+        #[test]
+        fn mapped_chunk_boundaries() {
+            let input = unterminated_input();
+            let mut map = MmapMut::map_anon(input.len()).unwrap();
+            map.copy_from_slice(input.as_bytes());
+            let map: Arc<AsRef<[u8]> + Send + Sync> = Arc::new(map.make_read_only().unwrap());
+            // Chunks can be shorter than a line, or end right after a newline.
+            for chunk_size in &[1, 7, 12, 100, 4096] {
+                let mut chunks = Vec::new();
+                assert!(Pipeline::map_chunks(map.clone(), *chunk_size, |data| {
+                    chunks.push(data.as_slice().to_vec());
+                    true
+                }));
+                assert_lines(&chunks, &input);
+            }
+        }
+
+        #[test]
+        fn compressed_chunk_boundaries() {
+            let input = unterminated_input();
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(input.as_bytes()).unwrap();
+            let compressed = encoder.finish().unwrap();
+            for buffer_size in &[1, 7, 100, 4096] {
+                let decoder = MultiGzDecoder::new(&compressed[..]);
+                let mut chunks = Vec::new();
+                let reader = BufReader::with_capacity(*buffer_size, decoder);
+                assert!(Pipeline::read_chunks(reader, |chunk| {
+                    chunks.push(chunk);
+                    true
+                })
+                .unwrap());
+                assert_lines(&chunks, &input);
+            }
+        }
+
+        #[test]
+        fn chunk_lines_read() {
+            for (data, expected) in &[
+                (&b"\n"[..], 1),
+                (b"\n\n", 2),
+                (b"a\nb\n", 2),
+                (b"a\nb", 2),
+                (b"a", 1),
+            ] {
+                let stats = Stats::default();
+                let chunk = Chunk {
+                    seq: 0,
+                    name: None,
+                    data: ChunkData::Owned(data.to_vec()),
+                };
+                let mut errors = operator::ErrorCounts::default();
+                let records = Pipeline::chunk_records(&chunk, &[], &stats, &mut errors, &mut []);
+                assert_eq!(stats.lines_read(), *expected, "{:?}", data);
+                assert_eq!(records.len(), *expected, "{:?}", data);
             }
-            row
+        }
+
+        #[test]
+        fn strict_stops_within_split() {
+            let query = "* | json | split vals as v | json from v | where n > 0";
+            let container = QueryContainer::new(query.to_string(), Box::new(SilentReporter));
+            let pipeline = Pipeline::new(&container, OutputFormat::Json).unwrap();
+            let mut ops: Vec<_> = pipeline
+                .pre_aggregates
+                .iter()
+                .map(|(name, op)| (name.clone(), op.build()))
+                .collect();
+            let stats = Stats {
+                strict: true,
+                ..Stats::default()
+            };
+            let mut errors = operator::ErrorCounts::default();
+            let mut records = Vec::new();
+            let line = r#"{"vals": [{"n": 1}, {"m": 2}, {"n": 3}]}"#;
+            Pipeline::proc_preagg(
+                Record::new(line),
+                &mut ops,
+                &stats,
+                &mut errors,
+                &mut |rec| records.push(rec),
+            );
+            // The third element comes after the error, so it isn't passed on.
+            assert_eq!(records.len(), 1);
+            assert_eq!(errors.total(), 1);
         }
     }
 }
@@ -8,17 +8,23 @@ use self::quantiles::ckms::CKMS;
 use self::serde_json::Value as JsonValue;
 use crate::data;
 use crate::data::{Aggregate, Record, Row};
+use crate::lang::Keep;
 use crate::operator::itertools::Itertools;
+use lazy_static::lazy_static;
+use std::any::Any;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::iter;
 use std::iter::FromIterator;
+use std::mem;
 
 type Data = HashMap<String, data::Value>;
 
-#[derive(Debug, Fail)]
+#[derive(Debug, Fail, Clone)]
 pub enum EvalError {
     #[fail(display = "No value for key {}", key)]
     NoValueForKey { key: String },
@@ -39,6 +45,118 @@ pub enum EvalError {
     ExpectedBoolean { found: String },
 }
 
+impl EvalError {
+    /// Describes the error without the details of the record it happened on, so errors of the
+    /// same kind can be counted together.  Reads as the end of "N records ...".
+    pub fn kind(&self) -> String {
+        match *self {
+            EvalError::NoValueForKey { ref key } => format!("missing key `{}`", key),
+            EvalError::UnexpectedNone { ref tpe } => format!("with None instead of {}", tpe),
+            EvalError::ExpectedJson { .. } => "that aren't JSON".to_string(),
+            EvalError::ExpectedString { .. } => "with a value that isn't a string".to_string(),
+            EvalError::ExpectedNumber { .. } => "with a value that isn't a number".to_string(),
+            EvalError::ExpectedBoolean { .. } => "with a value that isn't a boolean".to_string(),
+        }
+    }
+
+    /// Whether the errors have the same `kind`.
+    fn same_kind(&self, other: &EvalError) -> bool {
+        match (self, other) {
+            (EvalError::NoValueForKey { key: l }, EvalError::NoValueForKey { key: r }) => l == r,
+            (EvalError::UnexpectedNone { tpe: l }, EvalError::UnexpectedNone { tpe: r }) => l == r,
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+/// Counts evaluation errors by the operator they happened in and the kind of error.
+#[derive(Debug, Default, Clone)]
+pub struct ErrorCounts {
+    /// The operator, the first error of each kind and how many there were.  There are only ever a
+    /// few kinds, and errors can happen on every record, so this is searched rather than hashed
+    /// to avoid allocating for each error.
+    counts: Vec<(String, EvalError, usize)>,
+    /// The first error that was counted, prefixed with its operator.
+    first: Option<String>,
+}
+
+impl ErrorCounts {
+    pub fn add(&mut self, operator: &str, error: &EvalError) {
+        self.add_count(operator, error, 1);
+    }
+
+    fn add_count(&mut self, operator: &str, error: &EvalError, count: usize) {
+        if self.first.is_none() {
+            self.first = Some(format!("{}: {}", operator, error));
+        }
+        let existing = self
+            .counts
+            .iter_mut()
+            .find(|(op, kind, _)| op == operator && kind.same_kind(error));
+        match existing {
+            Some((_, _, total)) => *total += count,
+            None => self
+                .counts
+                .push((operator.to_string(), error.clone(), count)),
+        }
+    }
+
+    pub fn merge(&mut self, other: &ErrorCounts) {
+        if self.first.is_none() {
+            self.first = other.first.clone();
+        }
+        for (operator, error, count) in &other.counts {
+            self.add_count(operator, error, *count);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, _, count)| count).sum()
+    }
+
+    pub fn first(&self) -> Option<&str> {
+        self.first.as_deref()
+    }
+
+    /// One line per operator and kind of error, the most frequent first, e.g.
+    /// "where: 1,203 records missing key `status`".
+    pub fn summary(&self) -> Vec<String> {
+        let mut counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(operator, error, count)| (operator, error.kind(), *count))
+            .collect();
+        counts.sort_by(|(l_op, l_kind, l_count), (r_op, r_kind, r_count)| {
+            r_count
+                .cmp(l_count)
+                .then_with(|| (l_op, l_kind).cmp(&(r_op, r_kind)))
+        });
+        counts
+            .into_iter()
+            .map(|(operator, kind, count)| {
+                let records = if count == 1 { "record" } else { "records" };
+                format!("{}: {} {} {}", operator, group_digits(count), records, kind)
+            })
+            .collect()
+    }
+}
+
+/// Format a number with a comma between each group of three digits.
+fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let groups: Vec<&str> = digits
+        .as_bytes()
+        .rchunks(3)
+        .rev()
+        .map(|group| std::str::from_utf8(group).unwrap())
+        .collect();
+    groups.join(",")
+}
+
 pub trait Evaluatable<T>: Send + Sync + Clone {
     fn eval(&self, record: &Data) -> Result<T, EvalError>;
 }
@@ -50,6 +168,12 @@ pub trait EvaluatableBorrowed<T> {
 /// Trait for operators that are functional in nature and do not maintain state.
 pub trait UnaryPreAggFunction: Send + Sync {
     fn process(&self, rec: Record) -> Result<Option<Record>, EvalError>;
+
+    /// The columns of an aggregate after this function is applied to each of its rows, if they
+    /// follow from the columns before it.
+    fn columns(&self, _columns: &[String]) -> Option<Vec<String>> {
+        None
+    }
 }
 
 /// Get a column from the given record.
@@ -65,7 +189,9 @@ fn get_input<'a>(rec: &'a Record, col: &Option<Expr>) -> Result<&'a str, EvalErr
 
 /// Trait for operators that maintain state while processing records.
 pub trait UnaryPreAggOperator: Send + Sync {
-    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError>;
+    /// Process a record, passing the records that result to `emit`.  Most operators emit at
+    /// most one record for each record they process, but some turn a record into several.
+    fn process_mut(&mut self, rec: Record, emit: &mut FnMut(Record)) -> Result<(), EvalError>;
     /// Return any remaining records that may have been gathered by the operator.  This method
     /// will be called when there are no more new input records.
     fn drain(self: Box<Self>) -> Box<Iterator<Item = Record>> {
@@ -77,6 +203,20 @@ pub trait UnaryPreAggOperator: Send + Sync {
 /// state, the operator definition value can be cloned and returned.
 pub trait OperatorBuilder: Send + Sync {
     fn build(&self) -> Box<UnaryPreAggOperator>;
+
+    /// Returns true if the operators built by this definition don't keep any state between
+    /// records.  Stateless operators can process the input on several threads at once, while
+    /// stateful operators need to see the records in their original order.
+    fn is_stateless(&self) -> bool {
+        false
+    }
+
+    /// The columns of an aggregate after the operators built by this definition are applied to
+    /// its rows, if they follow from the columns before them.  Otherwise they're worked out from
+    /// the rows that come out, with any new columns after the existing ones.
+    fn columns(&self, _columns: &[String]) -> Option<Vec<String>> {
+        None
+    }
 }
 
 /// A trivial OperatorBuilder implementation for functional traits since they don't need to
@@ -89,22 +229,36 @@ where
         // TODO: eliminate the clone since a functional operator definition could be shared.
         Box::new((*self).clone())
     }
+
+    fn is_stateless(&self) -> bool {
+        true
+    }
+
+    fn columns(&self, columns: &[String]) -> Option<Vec<String>> {
+        UnaryPreAggFunction::columns(self, columns)
+    }
 }
 
 /// Adapter for pre-aggregate operators to be used on the output of aggregate operators.
 pub struct PreAggAdapter {
+    /// The name of the operator, for its errors.
+    name: String,
     op_builder: Box<OperatorBuilder>,
     state: Aggregate,
+    /// Errors from the rows of the last aggregate that was processed.
+    errors: ErrorCounts,
 }
 
 impl PreAggAdapter {
-    pub fn new(op_builder: Box<OperatorBuilder>) -> Self {
+    pub fn new(name: &str, op_builder: Box<OperatorBuilder>) -> Self {
         PreAggAdapter {
+            name: name.to_string(),
             op_builder,
             state: Aggregate {
                 columns: Vec::new(),
                 data: Vec::new(),
             },
+            errors: ErrorCounts::default(),
         }
     }
 }
@@ -115,8 +269,11 @@ impl<T> UnaryPreAggOperator for T
 where
     T: UnaryPreAggFunction,
 {
-    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
-        self.process(rec)
+    fn process_mut(&mut self, rec: Record, emit: &mut FnMut(Record)) -> Result<(), EvalError> {
+        if let Some(rec) = self.process(rec)? {
+            emit(rec);
+        }
+        Ok(())
     }
 }
 
@@ -125,24 +282,39 @@ impl AggregateOperator for PreAggAdapter {
         self.state.clone()
     }
 
+    fn take_errors(&mut self) -> ErrorCounts {
+        mem::take(&mut self.errors)
+    }
+
     fn process(&mut self, row: Row) {
         match row {
             Row::Record(_) => panic!("PreAgg adaptor should only be used after aggregates"),
             Row::Aggregate(agg) => {
+                let known_columns = self.op_builder.columns(&agg.columns);
                 let mut op = self.op_builder.build();
-                let mut processed_records: Vec<data::VMap> = {
-                    let records = agg
-                        .data
-                        .into_iter()
-                        .map(|vmap| data::Record {
-                            data: vmap,
-                            raw: "".to_string(),
-                        })
-                        .flat_map(|rec| op.process_mut(rec).unwrap_or(None))
-                        .map(|rec| rec.data);
-                    records.collect()
-                };
+                let mut processed_records: Vec<data::VMap> = Vec::new();
+                // The whole aggregate is processed again each time, so start counting again too.
+                self.errors = ErrorCounts::default();
+                for vmap in agg.data {
+                    let rec = data::Record {
+                        data: vmap,
+                        raw: "".to_string(),
+                    };
+                    // Rows the operator fails on are dropped.
+                    if let Err(err) =
+                        op.process_mut(rec, &mut |rec| processed_records.push(rec.data))
+                    {
+                        self.errors.add(&self.name, &err);
+                    }
+                }
                 processed_records.extend(op.drain().map(|rec| rec.data));
+                if let Some(columns) = known_columns {
+                    self.state = Aggregate {
+                        data: processed_records,
+                        columns,
+                    };
+                    return;
+                }
                 let resulting_columns: Vec<String> = {
                     processed_records
                         .iter()
@@ -175,15 +347,67 @@ impl AggregateOperator for PreAggAdapter {
     }
 }
 
-pub trait AggregateOperator: Send + Sync {
+/// Helper trait for downcasting operator trait objects to their concrete type.
+pub trait AsAny {
+    fn as_any(&self) -> &Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
+pub trait AggregateOperator: AsAny + Send + Sync {
     fn emit(&self) -> data::Aggregate;
     fn process(&mut self, row: Row);
+
+    /// Create an empty copy of this operator that can aggregate a portion of the input on
+    /// another thread.  Returns None if the operator can't be split up this way.
+    fn split(&self) -> Option<Box<AggregateOperator>> {
+        None
+    }
+
+    /// Return the errors counted since the last call, if the operator counts them.
+    fn take_errors(&mut self) -> ErrorCounts {
+        ErrorCounts::default()
+    }
+
+    /// Merge the state of an operator created by `split()` into this one.  Only called on
+    /// operators that can be split.
+    fn merge(&mut self, _partial: &AggregateOperator) {}
+
+    /// Called on a split operator before it processes the records of a chunk, with the position
+    /// of the chunk in the input.  Partials are merged in whatever order they arrive, so an
+    /// operator that keeps the first of something uses this to tell which came first.
+    fn start_chunk(&mut self, _seq: usize) {}
 }
 
-pub trait AggregateFunction: Send + Sync {
+pub trait AggregateFunction: AsAny + Send + Sync {
     fn process(&mut self, rec: &Data) -> Result<(), EvalError>;
     fn emit(&self) -> data::Value;
     fn empty_box(&self) -> Box<AggregateFunction>;
+
+    /// Whether the function implements `merge`.  The worker threads only aggregate their part
+    /// of the input if every function in the aggregate can be merged, otherwise all the records
+    /// are aggregated on one thread.
+    fn can_merge(&self) -> bool {
+        false
+    }
+
+    /// Merge the state of another instance of the same function into this one.  The other
+    /// instance must have been created with `empty_box()` from the same definition.  Only
+    /// called if `can_merge` returns true.
+    fn merge(&mut self, _other: &AggregateFunction) {}
+}
+
+/// Downcast an AggregateFunction that is being merged to the concrete type of the function it's
+/// being merged into.
+pub fn downcast<T: 'static>(other: &AggregateFunction) -> &T {
+    other
+        .as_any()
+        .downcast_ref::<T>()
+        .expect("Only instances of the same aggregate function can be merged")
 }
 
 #[derive(Debug, Clone)]
@@ -351,6 +575,14 @@ impl AggregateFunction for Count {
     fn empty_box(&self) -> Box<AggregateFunction> {
         Box::new(Count::new())
     }
+
+    fn can_merge(&self) -> bool {
+        true
+    }
+
+    fn merge(&mut self, other: &AggregateFunction) {
+        self.count += downcast::<Count>(other).count;
+    }
 }
 
 pub struct Sum {
@@ -381,6 +613,14 @@ impl AggregateFunction for Sum {
     fn empty_box(&self) -> Box<AggregateFunction> {
         Box::new(Sum::empty(self.column.clone()))
     }
+
+    fn can_merge(&self) -> bool {
+        true
+    }
+
+    fn merge(&mut self, other: &AggregateFunction) {
+        self.total += downcast::<Sum>(other).total;
+    }
 }
 
 pub struct CountDistinct {
@@ -411,6 +651,15 @@ impl AggregateFunction for CountDistinct {
     fn empty_box(&self) -> Box<AggregateFunction> {
         Box::new(CountDistinct::empty(self.column.clone()))
     }
+
+    fn can_merge(&self) -> bool {
+        true
+    }
+
+    fn merge(&mut self, other: &AggregateFunction) {
+        let other = downcast::<CountDistinct>(other);
+        self.state.extend(other.state.iter().cloned());
+    }
 }
 
 pub struct Average {
@@ -444,6 +693,16 @@ impl AggregateFunction for Average {
     fn empty_box(&self) -> Box<AggregateFunction> {
         Box::new(Average::empty(self.column.clone()))
     }
+
+    fn can_merge(&self) -> bool {
+        true
+    }
+
+    fn merge(&mut self, other: &AggregateFunction) {
+        let other = downcast::<Average>(other);
+        self.total += other.total;
+        self.count += other.count;
+    }
 }
 
 pub struct Percentile {
@@ -483,6 +742,14 @@ impl AggregateFunction for Percentile {
     fn empty_box(&self) -> Box<AggregateFunction> {
         Box::new(Percentile::empty(self.column.clone(), self.percentile))
     }
+
+    fn can_merge(&self) -> bool {
+        true
+    }
+
+    fn merge(&mut self, other: &AggregateFunction) {
+        self.ckms += downcast::<Percentile>(other).ckms.clone();
+    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -549,27 +816,248 @@ impl AggregateOperator for Sorter {
     }
 }
 
+/// Number of characters in the longest bar drawn by `Chart`.
+const CHART_WIDTH: usize = 20;
+
+/// Eighths of a block, used to draw the end of a bar more precisely than whole characters allow.
+const PARTIAL_BLOCKS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// Levels of a sparkline, from the smallest value to the largest.
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Levels of a sparkline when unicode can't be used.
+const SPARK_ASCII: [char; 8] = ['_', '.', ',', '-', '~', '=', '*', '#'];
+
+lazy_static! {
+    /// Keys that look like a point in time: a date, a date and time, or a time of day.  They
+    /// sort in time order as strings.
+    static ref TIME_BUCKET: regex::Regex = regex::Regex::new(
+        r"^(\d{4}-\d{2}-\d{2}([T ]\d{2}(:\d{2}(:\d{2}(\.\d+)?)?)?Z?)?|\d{2}:\d{2}(:\d{2})?)$"
+    )
+    .unwrap();
+}
+
+/// Adds a `_chart` column with a bar proportional to the value of a numeric column.  If one of
+/// the keys of the aggregate is a time bucket, there's a sparkline of the values over time for
+/// each combination of the other keys instead.
+pub struct Chart {
+    column: Option<String>,
+    unicode: bool,
+    /// The key columns of the aggregate being charted.
+    keys: Vec<String>,
+    state: Aggregate,
+}
+
+impl Chart {
+    /// Chart the given column, or the last column if there isn't one.  Bars are drawn with
+    /// unicode block characters if `unicode` is set, otherwise with `#`.  `keys` are the key
+    /// columns of the aggregate, which are checked for a time bucket.
+    pub fn new(column: Option<String>, unicode: bool, keys: Vec<String>) -> Self {
+        Chart {
+            column,
+            unicode,
+            keys,
+            state: Aggregate {
+                columns: Vec::new(),
+                data: Vec::new(),
+            },
+        }
+    }
+
+    /// The first key whose values are all points in time, if there are at least two of them.
+    fn time_key(&self, column: &str) -> Option<&String> {
+        self.keys.iter().find(|key| {
+            if *key == column || !self.state.columns.contains(key) {
+                return false;
+            }
+            let mut buckets = HashSet::new();
+            for row in &self.state.data {
+                match row.get(*key) {
+                    Some(data::Value::Str(s)) if TIME_BUCKET.is_match(s) => {
+                        buckets.insert(s);
+                    }
+                    _ => return false,
+                }
+            }
+            buckets.len() > 1
+        })
+    }
+
+    /// One row for each combination of the keys other than `time`, with a sparkline of the
+    /// values in time order.  Buckets without a value are left blank.
+    fn sparklines(&self, time: &str, values: &[Option<f64>], max: f64) -> Aggregate {
+        let buckets: Vec<&str> = self
+            .state
+            .data
+            .iter()
+            .filter_map(|row| match row.get(time) {
+                Some(data::Value::Str(s)) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let group_keys: Vec<&String> = self
+            .keys
+            .iter()
+            .filter(|key| *key != time && self.state.columns.contains(key))
+            .collect();
+        let mut groups: Vec<(Vec<data::Value>, Vec<Option<f64>>)> = Vec::new();
+        let mut group_index: HashMap<Vec<data::Value>, usize> = HashMap::new();
+        for (row, value) in self.state.data.iter().zip(values) {
+            let key: Vec<data::Value> = group_keys
+                .iter()
+                .map(|key| row.get(*key).cloned().unwrap_or(data::Value::None))
+                .collect();
+            let index = *group_index.entry(key.clone()).or_insert_with(|| {
+                groups.push((key, vec![None; buckets.len()]));
+                groups.len() - 1
+            });
+            if let Some(data::Value::Str(bucket)) = row.get(time) {
+                if let Ok(pos) = buckets.binary_search(&bucket.as_str()) {
+                    groups[index].1[pos] = *value;
+                }
+            }
+        }
+        let levels = if self.unicode {
+            &SPARK_BLOCKS
+        } else {
+            &SPARK_ASCII
+        };
+        let data = groups
+            .into_iter()
+            .map(|(key, series)| {
+                let line: String = series
+                    .iter()
+                    .map(|value| match value {
+                        Some(value) if *value > 0.0 => {
+                            let level = (value / max * levels.len() as f64).ceil() as usize;
+                            levels[level.clamp(1, levels.len()) - 1]
+                        }
+                        _ => ' ',
+                    })
+                    .collect();
+                let mut row: data::VMap =
+                    group_keys.iter().map(|k| k.to_string()).zip(key).collect();
+                row.insert("_chart".to_string(), data::Value::Str(line));
+                row
+            })
+            .collect();
+        let mut columns: Vec<String> = group_keys.into_iter().cloned().collect();
+        columns.push("_chart".to_string());
+        Aggregate { columns, data }
+    }
+
+    fn bar(&self, value: f64, max: f64) -> String {
+        let width = value / max * CHART_WIDTH as f64;
+        if self.unicode {
+            let eighths = (width * 8.0).round() as usize;
+            let mut bar = "█".repeat(eighths / 8);
+            let partial = eighths % 8;
+            if partial > 0 {
+                bar.push(PARTIAL_BLOCKS[partial]);
+            }
+            bar
+        } else {
+            "#".repeat(width.round() as usize)
+        }
+    }
+}
+
+impl AggregateOperator for Chart {
+    fn emit(&self) -> data::Aggregate {
+        let column = match self.column.as_ref().or_else(|| self.state.columns.last()) {
+            Some(column) => column,
+            None => return self.state.clone(),
+        };
+        let values: Vec<Option<f64>> = self
+            .state
+            .data
+            .iter()
+            .map(|row| match row.get(column) {
+                Some(data::Value::Int(i)) => Some(*i as f64),
+                Some(data::Value::Float(f)) if !f.is_nan() => Some(f.into_inner()),
+                _ => None,
+            })
+            .collect();
+        let max = values.iter().flatten().cloned().fold(0.0, f64::max);
+        if let Some(time) = self.time_key(column) {
+            return self.sparklines(time, &values, max);
+        }
+        let data = self
+            .state
+            .data
+            .iter()
+            .zip(values)
+            .map(|(row, value)| {
+                let bar = match value {
+                    Some(value) if value > 0.0 => self.bar(value, max),
+                    _ => "".to_string(),
+                };
+                let mut row = row.clone();
+                row.insert("_chart".to_string(), data::Value::Str(bar));
+                row
+            })
+            .collect();
+        let mut columns = self.state.columns.clone();
+        columns.push("_chart".to_string());
+        Aggregate { columns, data }
+    }
+
+    fn process(&mut self, row: Row) {
+        match row {
+            Row::Aggregate(agg) => self.state = agg,
+            Row::Record(rec) => {
+                let mut new_columns: Vec<String> = rec
+                    .data
+                    .keys()
+                    .filter(|key| !self.state.columns.contains(key))
+                    .cloned()
+                    .collect();
+                new_columns.sort();
+                self.state.columns.extend(new_columns);
+                self.state.data.push(rec.data);
+            }
+        }
+    }
+}
+
 pub struct MultiGrouper {
     key_cols: Vec<Expr>,
     key_col_headers: Vec<String>,
-    agg_col: Vec<(String, Box<AggregateFunction>)>,
+    /// The column each function produces, the name of the function and the function.
+    agg_col: Vec<(String, String, Box<AggregateFunction>)>,
     // key-column values -> (agg_columns -> builders)
     state: HashMap<Vec<data::Value>, HashMap<String, Box<AggregateFunction>>>,
+    /// Errors from the aggregate functions, by the name of the function.
+    errors: ErrorCounts,
 }
 
 impl MultiGrouper {
     pub fn new(
         key_cols: &[Expr],
         key_col_headers: Vec<String>,
-        aggregators: Vec<(String, Box<AggregateFunction>)>,
+        aggregators: Vec<(String, String, Box<AggregateFunction>)>,
     ) -> Self {
         MultiGrouper {
             key_cols: key_cols.to_vec(),
             key_col_headers,
             agg_col: aggregators,
             state: HashMap::new(),
+            errors: ErrorCounts::default(),
         }
     }
+
+    /// Create the aggregate functions for a new group.
+    fn empty_row(
+        agg_col: &[(String, String, Box<AggregateFunction>)],
+    ) -> HashMap<String, Box<AggregateFunction>> {
+        agg_col
+            .iter()
+            .map(|&(ref k, _, ref v)| (k.to_owned(), v.empty_box()))
+            .collect()
+    }
+
     fn process_map(&mut self, data: &Data) {
         let key_values = self.key_cols.iter().map(|expr| expr.eval_borrowed(data));
         let key_columns: Vec<data::Value> = key_values
@@ -577,15 +1065,18 @@ impl MultiGrouper {
             .cloned()
             .collect();
         let agg_col = &self.agg_col;
-        let row = self.state.entry(key_columns).or_insert_with(|| {
-            agg_col
-                .iter()
-                .map(|&(ref k, ref v)| (k.to_owned(), v.empty_box()))
-                .collect()
-        });
-        for fun in row.values_mut() {
-            // TODO: #25 capture erorrs here instead of ignoring
-            let _ = fun.process(data);
+        let row = self
+            .state
+            .entry(key_columns)
+            .or_insert_with(|| MultiGrouper::empty_row(agg_col));
+        for (column, fun) in row.iter_mut() {
+            if let Err(err) = fun.process(data) {
+                let name = agg_col
+                    .iter()
+                    .find(|(agg_column, _, _)| agg_column == column)
+                    .map_or(column, |(_, name, _)| name);
+                self.errors.add(name, &err);
+            }
         }
     }
 }
@@ -616,13 +1107,208 @@ impl AggregateOperator for MultiGrouper {
                 self.process_map(&rec.data);
             }
             Row::Aggregate(ag) => {
+                // The whole aggregate is processed again each time, so start counting again too.
                 self.state.clear();
+                self.errors = ErrorCounts::default();
                 for row in ag.data {
                     self.process_map(&row);
                 }
             }
         }
     }
+
+    fn split(&self) -> Option<Box<AggregateOperator>> {
+        if !self.agg_col.iter().all(|(_, _, fun)| fun.can_merge()) {
+            return None;
+        }
+        let aggregators = self
+            .agg_col
+            .iter()
+            .map(|&(ref k, ref name, ref v)| (k.to_owned(), name.to_owned(), v.empty_box()))
+            .collect();
+        Some(Box::new(MultiGrouper::new(
+            &self.key_cols,
+            self.key_col_headers.clone(),
+            aggregators,
+        )))
+    }
+
+    fn merge(&mut self, partial: &AggregateOperator) {
+        let partial = partial
+            .as_any()
+            .downcast_ref::<MultiGrouper>()
+            .expect("Only a split MultiGrouper can be merged");
+        let agg_col = &self.agg_col;
+        for (key_columns, partial_row) in &partial.state {
+            let row = self
+                .state
+                .entry(key_columns.clone())
+                .or_insert_with(|| MultiGrouper::empty_row(agg_col));
+            for (k, fun) in row.iter_mut() {
+                fun.merge(&*partial_row[k]);
+            }
+        }
+        self.errors.merge(&partial.errors);
+    }
+
+    fn take_errors(&mut self) -> ErrorCounts {
+        mem::take(&mut self.errors)
+    }
+}
+
+lazy_static! {
+    /// Tokens that vary between lines produced by the same log statement.
+    static ref VARIABLE_TOKENS: regex::Regex = regex::Regex::new(concat!(
+        r"(?P<uuid>\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b)",
+        r"|(?P<ip>\b\d{1,3}(?:\.\d{1,3}){3}(?::\d+)?\b)",
+        r"|(?P<hex>\b(?:0x[0-9a-fA-F]+|[0-9a-fA-F]{8,})\b)",
+        r"|(?P<num>\d+(?:\.\d+)?)",
+    ))
+    .unwrap();
+}
+
+/// Replace the numbers, UUIDs, IP addresses and hex ids in a line with placeholders, so that
+/// lines from the same log statement share a template.
+pub fn template(line: &str) -> String {
+    VARIABLE_TOKENS
+        .replace_all(line, |caps: &regex::Captures| {
+            if caps.name("uuid").is_some() {
+                "<uuid>".to_string()
+            } else if caps.name("ip").is_some() {
+                "<ip>".to_string()
+            } else if let Some(hex) = caps.name("hex") {
+                let hex = hex.as_str();
+                let has_digit = hex.chars().any(|c| c.is_ascii_digit());
+                let has_letter = hex.chars().any(|c| c.is_ascii_alphabetic());
+                if hex.starts_with("0x") || (has_digit && has_letter) {
+                    "<hex>".to_string()
+                } else if has_digit {
+                    "<num>".to_string()
+                } else {
+                    // A word that happens to only use the letters a-f
+                    hex.to_string()
+                }
+            } else {
+                "<num>".to_string()
+            }
+        })
+        .into_owned()
+}
+
+/// Groups lines into templates with `template`, counting the lines that share each one and
+/// keeping the first as an example.  The templates are emitted in order, so that sorting by
+/// count keeps templates with the same count in order too.
+pub struct LogReduce {
+    input_column: Option<Expr>,
+    // template -> (count, example, position of the example)
+    state: BTreeMap<String, (i64, String, (usize, usize))>,
+    // The position of the next line: its chunk and its index in the chunk
+    position: (usize, usize),
+    errors: ErrorCounts,
+}
+
+impl LogReduce {
+    pub fn new(input_column: Option<Expr>) -> Self {
+        LogReduce {
+            input_column,
+            state: BTreeMap::new(),
+            position: (0, 0),
+            errors: ErrorCounts::default(),
+        }
+    }
+
+    fn process_line(&mut self, line: &str) {
+        let line = line.trim();
+        let position = self.position;
+        self.position.1 += 1;
+        let entry = self
+            .state
+            .entry(template(line))
+            .or_insert_with(|| (0, line.to_string(), position));
+        entry.0 += 1;
+    }
+}
+
+impl AggregateOperator for LogReduce {
+    fn emit(&self) -> Aggregate {
+        let columns = vec![
+            "_template".to_string(),
+            "_count".to_string(),
+            "_example".to_string(),
+        ];
+        let data = self
+            .state
+            .iter()
+            .map(|(template, (count, example, _))| {
+                let mut row = data::VMap::new();
+                row.insert("_template".to_string(), data::Value::Str(template.clone()));
+                row.insert("_count".to_string(), data::Value::Int(*count));
+                row.insert("_example".to_string(), data::Value::Str(example.clone()));
+                row
+            })
+            .collect();
+        Aggregate { columns, data }
+    }
+
+    fn process(&mut self, row: Row) {
+        match row {
+            Row::Record(rec) => match get_input(&rec, &self.input_column) {
+                Ok(line) => {
+                    let line = line.to_string();
+                    self.process_line(&line);
+                }
+                Err(err) => self.errors.add("logreduce", &err),
+            },
+            Row::Aggregate(ag) => {
+                // The whole aggregate is processed again each time, so start counting again too.
+                self.state.clear();
+                self.position = (0, 0);
+                self.errors = ErrorCounts::default();
+                let column = self
+                    .input_column
+                    .clone()
+                    .expect("The column to group after an aggregate is checked by typecheck");
+                for row in ag.data {
+                    let line: Result<&String, EvalError> = column.eval_borrowed(&row);
+                    match line {
+                        Ok(line) => self.process_line(line),
+                        Err(err) => self.errors.add("logreduce", &err),
+                    }
+                }
+            }
+        }
+    }
+
+    fn split(&self) -> Option<Box<AggregateOperator>> {
+        Some(Box::new(LogReduce::new(self.input_column.clone())))
+    }
+
+    fn merge(&mut self, partial: &AggregateOperator) {
+        let partial = partial
+            .as_any()
+            .downcast_ref::<LogReduce>()
+            .expect("Only a split LogReduce can be merged");
+        for (template, (count, example, position)) in &partial.state {
+            let entry = self
+                .state
+                .entry(template.clone())
+                .or_insert_with(|| (0, example.clone(), *position));
+            entry.0 += count;
+            if *position < entry.2 {
+                entry.1 = example.clone();
+                entry.2 = *position;
+            }
+        }
+        self.errors.merge(&partial.errors);
+    }
+
+    fn start_chunk(&mut self, seq: usize) {
+        self.position = (seq, 0);
+    }
+
+    fn take_errors(&mut self) -> ErrorCounts {
+        mem::take(&mut self.errors)
+    }
 }
 
 #[derive(Clone)]
@@ -757,13 +1443,13 @@ impl Total {
 }
 
 impl UnaryPreAggOperator for Total {
-    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+    fn process_mut(&mut self, rec: Record, emit: &mut FnMut(Record)) -> Result<(), EvalError> {
         // I guess this means there are cases when you need to both emit a warning _and_ a row, TODO
         // for now, we'll just emit the row
         let val: f64 = self.column.eval(&rec.data).unwrap_or(0.0);
         self.total += val;
-        let rec = rec.put(&self.output_column, data::Value::from_float(self.total));
-        Ok(Some(rec))
+        emit(rec.put(&self.output_column, data::Value::from_float(self.total)));
+        Ok(())
     }
 }
 
@@ -805,6 +1491,91 @@ impl UnaryPreAggFunction for Fields {
     }
 }
 
+/// Renames columns, keeping their place among the columns of an aggregate.
+#[derive(Clone)]
+pub struct Rename {
+    /// The old and new name of each column, applied in order.
+    columns: Vec<(String, String)>,
+}
+
+impl Rename {
+    pub fn new(columns: Vec<(String, String)>) -> Self {
+        Rename { columns }
+    }
+}
+
+impl UnaryPreAggFunction for Rename {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let mut rec = rec;
+        for (old, new) in &self.columns {
+            if let Some(value) = rec.data.remove(old) {
+                rec.data.insert(new.clone(), value);
+            }
+        }
+        Ok(Some(rec))
+    }
+
+    fn columns(&self, columns: &[String]) -> Option<Vec<String>> {
+        let mut renamed: Vec<String> = Vec::new();
+        for column in columns {
+            let column = self.columns.iter().fold(
+                column,
+                |column, (old, new)| if column == old { new } else { column },
+            );
+            // A column renamed to the name of another one replaces it.
+            if !renamed.contains(column) {
+                renamed.push(column.clone());
+            }
+        }
+        Some(renamed)
+    }
+}
+
+/// Replaces values that are none with a default.  The given columns are filled in if they're
+/// missing, and added after the other columns of an aggregate.
+#[derive(Clone)]
+pub struct FillNull {
+    value: data::Value,
+    columns: Vec<String>,
+}
+
+impl FillNull {
+    pub fn new(value: data::Value, columns: Vec<String>) -> Self {
+        FillNull { value, columns }
+    }
+}
+
+impl UnaryPreAggFunction for FillNull {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let mut rec = rec;
+        if self.columns.is_empty() {
+            for value in rec.data.values_mut() {
+                if *value == data::Value::None {
+                    *value = self.value.clone();
+                }
+            }
+        } else {
+            for column in &self.columns {
+                let value = rec.data.entry(column.clone()).or_insert(data::Value::None);
+                if *value == data::Value::None {
+                    *value = self.value.clone();
+                }
+            }
+        }
+        Ok(Some(rec))
+    }
+
+    fn columns(&self, columns: &[String]) -> Option<Vec<String>> {
+        let mut columns = columns.to_vec();
+        for column in &self.columns {
+            if !columns.contains(column) {
+                columns.push(column.clone());
+            }
+        }
+        Some(columns)
+    }
+}
+
 #[derive(Clone)]
 pub struct ParseJson {
     input_column: Option<Expr>,
@@ -827,26 +1598,93 @@ impl UnaryPreAggFunction for ParseJson {
             })?
         };
         let res = match json {
-            JsonValue::Object(map) => map.iter().fold(Some(rec), |record_opt, (k, v)| {
-                record_opt.and_then(|record| match v {
-                    &JsonValue::Number(ref num) => {
-                        if num.is_i64() {
-                            Some(record.put(k, data::Value::Int(num.as_i64().unwrap())))
-                        } else {
-                            Some(record.put(k, data::Value::from_float(num.as_f64().unwrap())))
-                        }
-                    }
-                    &JsonValue::String(ref s) => {
-                        Some(record.put(k, data::Value::Str(s.to_string())))
-                    }
-                    &JsonValue::Null => Some(record.put(k, data::Value::None)),
-                    &JsonValue::Bool(b) => Some(record.put(k, data::Value::Bool(b))),
-                    _other => Some(record.put(k, data::Value::Str(_other.to_string()))),
+            JsonValue::Object(map) => map
+                .iter()
+                .fold(rec, |record, (k, v)| record.put(k, json_value(v))),
+            _other => rec,
+        };
+        Ok(Some(res))
+    }
+}
+
+/// Convert a JSON value to a value for a record.  Arrays and objects are kept as JSON text.
+fn json_value(json: &JsonValue) -> data::Value {
+    match json {
+        JsonValue::Number(ref num) => {
+            if num.is_i64() {
+                data::Value::Int(num.as_i64().unwrap())
+            } else {
+                data::Value::from_float(num.as_f64().unwrap())
+            }
+        }
+        JsonValue::String(ref s) => data::Value::Str(s.to_string()),
+        JsonValue::Null => data::Value::None,
+        JsonValue::Bool(b) => data::Value::Bool(*b),
+        other => data::Value::Str(other.to_string()),
+    }
+}
+
+/// Splits a column into its elements and emits a record for each of them.  Without a
+/// separator, a JSON array is split into its elements and anything else on commas.
+#[derive(Clone)]
+pub struct Split {
+    column: String,
+    separator: Option<String>,
+    output_column: String,
+}
+
+impl Split {
+    pub fn new(column: String, separator: Option<String>, output_column: String) -> Self {
+        Split {
+            column,
+            separator,
+            output_column,
+        }
+    }
+
+    fn elements(&self, value: &data::Value) -> Vec<data::Value> {
+        let text = match value {
+            data::Value::Str(text) => text,
+            other => return vec![other.clone()],
+        };
+        if self.separator.is_none() && text.trim_start().starts_with('[') {
+            if let Ok(JsonValue::Array(elements)) = serde_json::from_str(text) {
+                return elements.iter().map(json_value).collect();
+            }
+        }
+        let separator = self.separator.as_ref().map_or(",", String::as_str);
+        text.split(separator)
+            .map(|element| data::Value::from_string(element.trim()))
+            .collect()
+    }
+}
+
+/// Split doesn't keep any state, but it's not a `UnaryPreAggFunction` since it can emit several
+/// records.
+impl OperatorBuilder for Split {
+    fn build(&self) -> Box<UnaryPreAggOperator> {
+        Box::new(self.clone())
+    }
+
+    fn is_stateless(&self) -> bool {
+        true
+    }
+}
+
+impl UnaryPreAggOperator for Split {
+    fn process_mut(&mut self, rec: Record, emit: &mut FnMut(Record)) -> Result<(), EvalError> {
+        let elements = match rec.data.get(&self.column) {
+            Some(value) => self.elements(value),
+            None => {
+                return Err(EvalError::NoValueForKey {
+                    key: self.column.clone(),
                 })
-            }),
-            _other => Some(rec),
+            }
         };
-        Ok(res)
+        for element in elements {
+            emit(rec.clone().put(&self.output_column, element));
+        }
+        Ok(())
     }
 }
 
@@ -898,7 +1736,7 @@ impl OperatorBuilder for LimitDef {
 }
 
 impl UnaryPreAggOperator for Limit {
-    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+    fn process_mut(&mut self, rec: Record, emit: &mut FnMut(Record)) -> Result<(), EvalError> {
         match self {
             Limit::Head {
                 ref mut index,
@@ -907,10 +1745,9 @@ impl UnaryPreAggOperator for Limit {
                 (*index) += 1;
 
                 if index <= limit {
-                    Ok(Some(rec))
-                } else {
-                    Ok(None)
+                    emit(rec);
                 }
+                Ok(())
             }
             Limit::Tail {
                 ref mut queue,
@@ -921,7 +1758,7 @@ impl UnaryPreAggOperator for Limit {
                 }
                 queue.push_back(rec);
 
-                Ok(None)
+                Ok(())
             }
         }
     }
@@ -934,6 +1771,116 @@ impl UnaryPreAggOperator for Limit {
     }
 }
 
+/// The definition for a dedup operator.  Without a window, every key that was seen is
+/// remembered, so memory grows with the number of distinct keys.
+pub struct DedupDef {
+    key_cols: Vec<Expr>,
+    keep: Keep,
+    within: Option<usize>,
+}
+
+impl DedupDef {
+    pub fn new(key_cols: Vec<Expr>, keep: Keep, within: Option<usize>) -> Self {
+        DedupDef {
+            key_cols,
+            keep,
+            within,
+        }
+    }
+}
+
+impl OperatorBuilder for DedupDef {
+    fn build(&self) -> Box<UnaryPreAggOperator> {
+        Box::new(Dedup {
+            key_cols: self.key_cols.clone(),
+            keep: self.keep,
+            within: self.within,
+            index: 0,
+            seen: HashMap::new(),
+            window: VecDeque::new(),
+            pending: BTreeMap::new(),
+        })
+    }
+}
+
+/// The state for a dedup operator, which drops records with the same key as another record.
+/// With `Keep::First` records are passed on as soon as they arrive, with `Keep::Last` they're
+/// held until they leave the window, or until the input ends if there is no window.
+pub struct Dedup {
+    key_cols: Vec<Expr>,
+    keep: Keep,
+    /// Only records this close to each other are duplicates.
+    within: Option<usize>,
+    /// The index of the next record.
+    index: usize,
+    /// The index of the latest record with each key.
+    seen: HashMap<Vec<data::Value>, usize>,
+    /// The indexes and keys of the records in the window, oldest first.
+    window: VecDeque<(usize, Vec<data::Value>)>,
+    /// The records that will be kept unless a record with the same key follows, by index.
+    pending: BTreeMap<usize, Record>,
+}
+
+impl Dedup {
+    /// The key columns of the record.  Without key columns, the whole line is the key, or the
+    /// whole row after an aggregate.
+    fn key(&self, rec: &Record) -> Result<Vec<data::Value>, EvalError> {
+        if !self.key_cols.is_empty() {
+            self.key_cols
+                .iter()
+                .map(|expr| expr.eval_borrowed(&rec.data).cloned())
+                .collect()
+        } else if !rec.raw.is_empty() {
+            Ok(vec![data::Value::Str(rec.raw.clone())])
+        } else {
+            Ok(rec
+                .data
+                .iter()
+                .sorted_by(|(l, _), (r, _)| l.cmp(r))
+                .map(|(_, value)| value.clone())
+                .collect())
+        }
+    }
+}
+
+impl UnaryPreAggOperator for Dedup {
+    fn process_mut(&mut self, rec: Record, emit: &mut FnMut(Record)) -> Result<(), EvalError> {
+        let key = self.key(&rec)?;
+        let index = self.index;
+        self.index += 1;
+        let previous = self.seen.insert(key.clone(), index);
+        let mut expired = None;
+        if let Some(within) = self.within {
+            self.window.push_back((index, key));
+            if self.window.len() > within {
+                let (old_index, old_key) = self.window.pop_front().unwrap();
+                if self.seen.get(&old_key) == Some(&old_index) {
+                    self.seen.remove(&old_key);
+                }
+                expired = Some(old_index);
+            }
+        }
+        match self.keep {
+            Keep::First if previous.is_some() => (),
+            Keep::First => emit(rec),
+            Keep::Last => {
+                if let Some(previous) = previous {
+                    self.pending.remove(&previous);
+                }
+                self.pending.insert(index, rec);
+                if let Some(rec) = expired.and_then(|index| self.pending.remove(&index)) {
+                    emit(rec);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn drain(self: Box<Self>) -> Box<Iterator<Item = Record>> {
+        Box::new(self.pending.into_values())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1107,8 +2054,11 @@ mod tests {
 
     #[test]
     fn count_no_groups() {
-        let ops: Vec<(String, Box<AggregateFunction>)> =
-            vec![("_count".to_string(), Box::new(Count::new()))];
+        let ops: Vec<(String, String, Box<AggregateFunction>)> = vec![(
+            "_count".to_string(),
+            "count".to_string(),
+            Box::new(Count::new()),
+        )];
         let mut count_agg = MultiGrouper::new(&[], vec![], ops);
         (0..10)
             .map(|n| Record::new(&n.to_string()))
@@ -1130,11 +2080,20 @@ mod tests {
 
     #[test]
     fn multi_grouper() {
-        let ops: Vec<(String, Box<AggregateFunction>)> = vec![
-            ("_count".to_string(), Box::new(Count::new())),
-            ("_sum".to_string(), Box::new(Sum::empty("v1"))),
+        let ops: Vec<(String, String, Box<AggregateFunction>)> = vec![
+            (
+                "_count".to_string(),
+                "count".to_string(),
+                Box::new(Count::new()),
+            ),
+            (
+                "_sum".to_string(),
+                "sum".to_string(),
+                Box::new(Sum::empty("v1")),
+            ),
             (
                 "_distinct".to_string(),
+                "count_distinct".to_string(),
                 Box::new(CountDistinct::empty("v1")),
             ),
         ];
@@ -1197,10 +2156,190 @@ mod tests {
         );
     }
 
+    #[test]
+    fn multi_grouper_merge() {
+        let ops: Vec<(String, String, Box<AggregateFunction>)> = vec![
+            (
+                "_count".to_string(),
+                "count".to_string(),
+                Box::new(Count::new()),
+            ),
+            (
+                "_average".to_string(),
+                "average".to_string(),
+                Box::new(Average::empty("v1")),
+            ),
+            (
+                "_distinct".to_string(),
+                "count_distinct".to_string(),
+                Box::new(CountDistinct::empty("v1")),
+            ),
+            (
+                "p50".to_string(),
+                "percentile".to_string(),
+                Box::new(Percentile::empty("v1", 0.5)),
+            ),
+        ];
+
+        let grouper = MultiGrouper::new(
+            &[Expr::Column("k1".to_string())],
+            vec!["k1".to_string()],
+            ops,
+        );
+        let mut merged = grouper.split().unwrap();
+        for range in &[(0..10), (5..15), (100..101)] {
+            let mut partial = grouper.split().unwrap();
+            range.clone().for_each(|n| {
+                let rec = Record::new(&n.to_string());
+                let rec = rec.put("k1", data::Value::Str((n % 2 == 0).to_string()));
+                let rec = rec.put("v1", data::Value::Int(n));
+                partial.process(Row::Record(rec));
+            });
+            merged.merge(&*partial);
+        }
+        let agg = merged.emit();
+        let mut sorted_data = agg.data.clone();
+        let ordering = Record::ordering(vec!["k1".to_string()]);
+        sorted_data.sort_by(|l, r| ordering(l, r));
+        assert_eq!(
+            sorted_data,
+            vec![
+                hashmap! {
+                    "k1".to_string() => data::Value::Str("false".to_string()),
+                    "_count".to_string() => data::Value::Int(10),
+                    "_average".to_string() => data::Value::Int(7),
+                    "_distinct".to_string() => data::Value::Int(7),
+                    "p50".to_string() => data::Value::Int(7),
+                },
+                hashmap! {
+                    "k1".to_string() => data::Value::Str("true".to_string()),
+                    "_count".to_string() => data::Value::Int(11),
+                    "_average".to_string() => data::Value::from_float(170.0 / 11.0),
+                    "_distinct".to_string() => data::Value::Int(9),
+                    "p50".to_string() => data::Value::Int(8),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn logreduce_template() {
+        assert_eq!(
+            template("connected to 10.0.0.1:8080 in 35ms"),
+            "connected to <ip> in <num>ms"
+        );
+        assert_eq!(
+            template("request 3f2a9c1e-1b2c-4d5e-8f90-abcdef012345 failed after 1.5s"),
+            "request <uuid> failed after <num>s"
+        );
+        assert_eq!(
+            template("freed 0xdeadbeef, commit 9fceb02d0ae598e9"),
+            "freed <hex>, commit <hex>"
+        );
+        assert_eq!(template("decade facade"), "decade facade");
+    }
+
+    #[test]
+    fn logreduce_merge() {
+        let reducer = LogReduce::new(None);
+        let mut merged = reducer.split().unwrap();
+        // The partials arrive out of order, but the examples come from the earliest chunk.
+        for (seq, lines) in &[
+            (1, &["took 5ms", "took 10ms", "done"][..]),
+            (0, &["took 7ms"]),
+        ] {
+            let mut partial = reducer.split().unwrap();
+            partial.start_chunk(*seq);
+            for line in lines.iter() {
+                partial.process(Row::Record(Record::new(line)));
+            }
+            merged.merge(&*partial);
+        }
+        let agg = merged.emit();
+        let mut sorted_data = agg.data.clone();
+        let ordering = Record::ordering(vec!["_count".to_string()]);
+        sorted_data.sort_by(|l, r| ordering(l, r));
+        assert_eq!(
+            sorted_data,
+            vec![
+                hashmap! {
+                    "_template".to_string() => data::Value::Str("done".to_string()),
+                    "_count".to_string() => data::Value::Int(1),
+                    "_example".to_string() => data::Value::Str("done".to_string()),
+                },
+                hashmap! {
+                    "_template".to_string() => data::Value::Str("took <num>ms".to_string()),
+                    "_count".to_string() => data::Value::Int(3),
+                    "_example".to_string() => data::Value::Str("took 7ms".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn logreduce_ties() {
+        let mut reducer = LogReduce::new(None);
+        for line in &["retrying", "connected", "done"] {
+            reducer.process(Row::Record(Record::new(line)));
+        }
+        let mut sorter = Sorter::new(vec!["_count".to_string()], SortDirection::Descending);
+        sorter.process(Row::Aggregate(reducer.emit()));
+        let templates: Vec<_> = sorter
+            .emit()
+            .data
+            .iter()
+            .map(|row| row["_template"].clone())
+            .collect();
+        assert_eq!(
+            templates,
+            vec![
+                data::Value::Str("connected".to_string()),
+                data::Value::Str("done".to_string()),
+                data::Value::Str("retrying".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_grouper_errors() {
+        let ops: Vec<(String, String, Box<AggregateFunction>)> = vec![(
+            "_sum".to_string(),
+            "sum".to_string(),
+            Box::new(Sum::empty("v1")),
+        )];
+        let grouper = MultiGrouper::new(&[], vec![], ops);
+        let mut merged = grouper.split().unwrap();
+        for n in 0..1500 {
+            let mut partial = grouper.split().unwrap();
+            let rec = Record::new(&n.to_string());
+            let rec = if n % 3 == 0 {
+                rec.put("v1", data::Value::Str("x".to_string()))
+            } else {
+                rec
+            };
+            partial.process(Row::Record(rec));
+            merged.merge(&*partial);
+        }
+        let errors = merged.take_errors();
+        assert_eq!(errors.total(), 1500);
+        assert_eq!(
+            errors.summary(),
+            vec![
+                "sum: 1,000 records missing key `v1`",
+                "sum: 500 records with a value that isn't a number",
+            ]
+        );
+        assert_eq!(errors.first(), Some("sum: Expected number, found x"));
+        assert!(merged.take_errors().is_empty());
+    }
+
     #[test]
     fn count_groups() {
-        let ops: Vec<(String, Box<AggregateFunction>)> =
-            vec![("_count".to_string(), Box::new(Count::new()))];
+        let ops: Vec<(String, String, Box<AggregateFunction>)> = vec![(
+            "_count".to_string(),
+            "count".to_string(),
+            Box::new(Count::new()),
+        )];
         let mut count_agg = MultiGrouper::new(
             &[Expr::Column("k1".to_string())],
             vec!["k1".to_string()],
@@ -1279,10 +2418,118 @@ mod tests {
         assert_eq!(sorter.emit(), revagg);
     }
 
+    #[test]
+    fn chart_aggregate() {
+        let agg = Aggregate::new(
+            &["kc1".to_string()],
+            "count".to_string(),
+            &[
+                (
+                    hashmap! {"kc1".to_string() => "k1".to_string()},
+                    Value::Int(500),
+                ),
+                (
+                    hashmap! {"kc1".to_string() => "k2".to_string()},
+                    Value::Int(130),
+                ),
+                (
+                    hashmap! {"kc1".to_string() => "k3".to_string()},
+                    Value::Int(0),
+                ),
+            ],
+        );
+        let bars = |chart: &Chart| -> Vec<Value> {
+            chart
+                .emit()
+                .data
+                .iter()
+                .map(|row| row["_chart"].clone())
+                .collect()
+        };
+
+        let mut chart = Chart::new(None, true, vec!["kc1".to_string()]);
+        chart.process(data::Row::Aggregate(agg.clone()));
+        assert_eq!(chart.emit().columns, vec!["kc1", "count", "_chart"]);
+        assert_eq!(
+            bars(&chart),
+            vec![
+                Value::Str("█".repeat(20)),
+                Value::Str("█████▎".to_string()),
+                Value::Str("".to_string()),
+            ]
+        );
+
+        let mut chart = Chart::new(Some("count".to_string()), false, vec![]);
+        chart.process(data::Row::Aggregate(agg));
+        assert_eq!(
+            bars(&chart),
+            vec![
+                Value::Str("#".repeat(20)),
+                Value::Str("#####".to_string()),
+                Value::Str("".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn chart_time_buckets() {
+        let rows = &[
+            ("10:00", "web", 4),
+            ("10:00", "db", 1),
+            ("10:01", "web", 8),
+            ("10:02", "web", 2),
+            ("10:02", "db", 7),
+        ];
+        let agg = Aggregate {
+            columns: vec![
+                "minute".to_string(),
+                "host".to_string(),
+                "_count".to_string(),
+            ],
+            data: rows
+                .iter()
+                .map(|(minute, host, count)| {
+                    hashmap! {
+                        "minute".to_string() => Value::from_string(minute),
+                        "host".to_string() => Value::from_string(host),
+                        "_count".to_string() => Value::Int(*count),
+                    }
+                })
+                .collect(),
+        };
+        let keys = vec!["minute".to_string(), "host".to_string()];
+        let mut chart = Chart::new(None, true, keys.clone());
+        chart.process(Row::Aggregate(agg.clone()));
+        let sparklines = chart.emit();
+        assert_eq!(sparklines.columns, vec!["host", "_chart"]);
+        assert_eq!(
+            sparklines.data,
+            vec![
+                hashmap! {
+                    "host".to_string() => Value::from_string("web"),
+                    "_chart".to_string() => Value::from_string("▄█▂"),
+                },
+                hashmap! {
+                    "host".to_string() => Value::from_string("db"),
+                    "_chart".to_string() => Value::from_string("▁ ▇"),
+                },
+            ]
+        );
+
+        let mut chart = Chart::new(None, false, keys);
+        chart.process(Row::Aggregate(agg.clone()));
+        assert_eq!(chart.emit().data[0]["_chart"], Value::from_string("-#."));
+
+        // Without a time bucket among the keys, each row gets a bar.
+        let mut chart = Chart::new(None, false, vec!["host".to_string()]);
+        chart.process(Row::Aggregate(agg));
+        assert_eq!(chart.emit().data[1]["_chart"], Value::from_string("###"));
+    }
+
     #[test]
     fn test_agg_adapter() {
         let where_op = Where::new(true);
-        let adapted = PreAggAdapter::new(Box::new(where_op));
+        let adapted = PreAggAdapter::new("where", Box::new(where_op));
         let mut adapted: Box<AggregateOperator> = Box::new(adapted);
         let agg = Aggregate::new(
             &["kc1".to_string(), "kc2".to_string()],
@@ -1306,14 +2553,144 @@ mod tests {
         );
         let _: () = adapted.process(Row::Aggregate(agg.clone()));
         assert_eq!(adapted.emit(), agg.clone());
+
+        // The rows the operator fails on are counted under its name, for the last aggregate only.
+        let where_op = Where::new("missing".to_string());
+        let mut adapted = PreAggAdapter::new("where", Box::new(where_op));
+        adapted.process(Row::Aggregate(agg.clone()));
+        adapted.process(Row::Aggregate(agg));
+        assert!(adapted.emit().data.is_empty());
+        assert_eq!(
+            adapted.take_errors().summary(),
+            vec!["where: 2 records missing key `missing`"]
+        );
+    }
+
+    #[test]
+    fn rename_fillnull() {
+        let agg = Aggregate {
+            columns: vec!["kc1".to_string(), "kc2".to_string(), "count".to_string()],
+            data: vec![hashmap! {
+                "kc1".to_string() => Value::None,
+                "kc2".to_string() => Value::from_string("k2"),
+                "count".to_string() => Value::Int(100),
+            }],
+        };
+        let mut rename = PreAggAdapter::new(
+            "rename",
+            Box::new(Rename::new(vec![
+                ("kc2".to_string(), "key".to_string()),
+                ("kc1".to_string(), "count".to_string()),
+            ])),
+        );
+        rename.process(Row::Aggregate(agg.clone()));
+        assert_eq!(
+            rename.emit(),
+            Aggregate {
+                columns: vec!["count".to_string(), "key".to_string()],
+                data: vec![hashmap! {
+                    "key".to_string() => Value::from_string("k2"),
+                    "count".to_string() => Value::None,
+                }],
+            }
+        );
+
+        let mut fill_all =
+            PreAggAdapter::new("fillnull", Box::new(FillNull::new(Value::Int(0), vec![])));
+        fill_all.process(Row::Aggregate(agg.clone()));
+        assert_eq!(fill_all.emit().columns, agg.columns);
+        assert_eq!(fill_all.emit().data[0]["kc1"], Value::Int(0));
+
+        let fill = FillNull::new(Value::from_string("none"), vec!["kc3".to_string()]);
+        let rec = fill.process(Record::new("")).unwrap().unwrap();
+        assert_eq!(rec.data["kc3"], Value::from_string("none"));
+        let mut fill = PreAggAdapter::new("fillnull", Box::new(fill));
+        fill.process(Row::Aggregate(agg.clone()));
+        assert_eq!(fill.emit().columns, vec!["kc1", "kc2", "count", "kc3"]);
+        assert_eq!(fill.emit().data[0]["kc1"], Value::None);
+    }
+
+    #[test]
+    fn split() {
+        let split = |split: Split, value: data::Value| {
+            let mut op = split.build();
+            let mut elements = Vec::new();
+            let rec = Record::new("").put("id", Value::Int(1)).put("x", value);
+            op.process_mut(rec, &mut |rec| {
+                assert_eq!(rec.data["id"], Value::Int(1));
+                elements.push(rec.data["item"].clone());
+            })
+            .unwrap();
+            elements
+        };
+        let by_default = || Split::new("x".to_string(), None, "item".to_string());
+        assert_eq!(
+            split(by_default(), Value::from_string(r#"["a", 2, null]"#)),
+            [Value::from_string("a"), Value::Int(2), Value::None]
+        );
+        assert_eq!(
+            split(by_default(), Value::from_string("a, 2")),
+            [Value::from_string("a"), Value::Int(2)]
+        );
+        assert_eq!(split(by_default(), Value::from_string("[]")), []);
+        assert_eq!(split(by_default(), Value::Int(5)), [Value::Int(5)]);
+        assert_eq!(
+            split(
+                Split::new("x".to_string(), Some(";".to_string()), "item".to_string()),
+                Value::from_string("[a,b];c")
+            ),
+            [Value::from_string("[a,b]"), Value::from_string("c")]
+        );
+
+        let mut op = by_default().build();
+        match op.process_mut(Record::new(""), &mut |_| panic!("Nothing to split")) {
+            Err(EvalError::NoValueForKey { ref key }) if key == "x" => (),
+            other => panic!("Expected a missing key, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dedup() {
+        let run = |def: DedupDef| {
+            let mut op = def.build();
+            let mut kept: Vec<String> = Vec::new();
+            for (n, host) in ["a", "b", "a", "c", "b", "a"].iter().enumerate() {
+                let rec = Record::new(&n.to_string()).put("host", data::Value::from_string(host));
+                op.process_mut(rec, &mut |rec| kept.push(rec.raw)).unwrap();
+            }
+            kept.extend(op.drain().map(|rec| rec.raw));
+            kept
+        };
+        let host = || vec![Expr::Column("host".to_string())];
+        assert_eq!(
+            run(DedupDef::new(host(), Keep::First, None)),
+            ["0", "1", "3"]
+        );
+        assert_eq!(
+            run(DedupDef::new(host(), Keep::Last, None)),
+            ["3", "4", "5"]
+        );
+        assert_eq!(
+            run(DedupDef::new(host(), Keep::First, Some(2))),
+            ["0", "1", "3", "4", "5"]
+        );
+        assert_eq!(
+            run(DedupDef::new(host(), Keep::Last, Some(2))),
+            ["1", "2", "3", "4", "5"]
+        );
+        // Every line is different
+        assert_eq!(run(DedupDef::new(vec![], Keep::First, None)).len(), 6);
     }
 
     #[test]
     fn test_total() {
-        let mut total_op = PreAggAdapter::new(Box::new(TotalDef::new(
-            Expr::Column("count".to_string()),
-            "_total".to_string(),
-        )));
+        let mut total_op = PreAggAdapter::new(
+            "total",
+            Box::new(TotalDef::new(
+                Expr::Column("count".to_string()),
+                "_total".to_string(),
+            )),
+        );
         let agg = Aggregate::new(
             &["kc1".to_string(), "kc2".to_string()],
             "count".to_string(),
@@ -0,0 +1,171 @@
+//! Bind the `$name` parameters in a query to the values given when it's run, so the same query
+//! can be reused with a different host name or threshold.
+use crate::data::Value;
+use crate::errors::{did_you_mean, ErrorBuilder};
+use crate::lang::{BinaryOp, ComparisonOp, Expr, Positioned, Query, QueryPosition};
+
+#[derive(Debug, Fail)]
+pub enum ParamError {
+    #[fail(display = "No value for parameter ${}", name)]
+    Unbound { name: String },
+
+    #[fail(
+        display = "Parameter ${} is compared as a number, but its value `{}` isn't a number",
+        name, value
+    )]
+    NotANumber { name: String, value: String },
+}
+
+/// Replace the parameters in the query with their values.  Values are typed the same way as
+/// fields parsed from the input, so `5` is a number and `web-1` is a string.  Parameters that are
+/// compared with `<`, `>`, `<=` or `>=` have to be numbers.  Every parameter without a valid
+/// value is reported, and the position of the first one is returned.
+pub fn bind<T: ErrorBuilder>(
+    query: &mut Query,
+    params: &[(String, String)],
+    error_builder: &T,
+) -> Result<(), QueryPosition> {
+    let mut binder = Binder {
+        params,
+        used: vec![false; params.len()],
+        error: None,
+        error_builder,
+    };
+    for expr in query.exprs_mut() {
+        binder.bind(expr, false);
+    }
+    for ((name, _), used) in params.iter().zip(&binder.used) {
+        if !used {
+            error_builder
+                .report_error_for(format!("Parameter ${} isn't used in the query", name))
+                .send_warning();
+        }
+    }
+    match binder.error {
+        Some(pos) => Err(pos),
+        None => Ok(()),
+    }
+}
+
+struct Binder<'a, T> {
+    params: &'a [(String, String)],
+    used: Vec<bool>,
+    error: Option<QueryPosition>,
+    error_builder: &'a T,
+}
+
+impl<'a, T: ErrorBuilder> Binder<'a, T> {
+    fn bind(&mut self, expr: &mut Expr, numeric: bool) {
+        let value = match expr {
+            Expr::Param(param) => match self.value(param, numeric) {
+                Some(value) => value,
+                None => return,
+            },
+            Expr::Unary { operand, .. } => return self.bind(operand, false),
+            Expr::Binary { op, left, right } => {
+                let numeric = match op {
+                    BinaryOp::Comparison(ComparisonOp::Eq)
+                    | BinaryOp::Comparison(ComparisonOp::Neq) => false,
+                    BinaryOp::Comparison(_) => true,
+                };
+                self.bind(left, numeric);
+                self.bind(right, numeric);
+                return;
+            }
+            Expr::Column(_) | Expr::Value(_) => return,
+        };
+        *expr = Expr::Value(value);
+    }
+
+    /// The value of a parameter, or `None` if it doesn't have a valid one.  The last value
+    /// given for a parameter wins.
+    fn value(&mut self, param: &Positioned<String>, numeric: bool) -> Option<Value> {
+        let name = &param.value;
+        let index = self.params.iter().rposition(|(n, _)| n == name);
+        let error = match index {
+            Some(index) => {
+                self.used[index] = true;
+                let text = &self.params[index].1;
+                let value = Value::from_string(text);
+                match value {
+                    Value::Int(_) | Value::Float(_) => return Some(value),
+                    _ if !numeric => return Some(value),
+                    _ => ParamError::NotANumber {
+                        name: name.clone(),
+                        value: text.clone(),
+                    },
+                }
+            }
+            None => ParamError::Unbound { name: name.clone() },
+        };
+
+        let mut report = self.error_builder.report_error_for(&error);
+        match error {
+            ParamError::Unbound { .. } => {
+                report = report.with_code_pointer(param, "");
+                let names: Vec<&str> = self.params.iter().map(|(n, _)| n.as_str()).collect();
+                report = match did_you_mean(name, &names) {
+                    Some(choice) => report.with_resolution(format!("Did you mean ${}?", choice)),
+                    None => report.with_resolution(format!(
+                        "Give it a value with `--param {}=<value>`",
+                        name
+                    )),
+                };
+            }
+            ParamError::NotANumber { .. } => {
+                report = report.with_code_pointer(param, "Compared as a number here");
+            }
+        }
+        report.send_report();
+        if self.error.is_none() {
+            self.error = Some(param.start_pos.clone());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ErrorReporter;
+    use crate::pipeline::{OutputFormat, Pipeline, QueryContainer, QueryError};
+
+    struct Silent;
+    impl ErrorReporter for Silent {}
+
+    fn compile(query: &str, params: &[(&str, &str)]) -> Result<Pipeline, Vec<QueryError>> {
+        let params = params
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect();
+        let container =
+            QueryContainer::new(query.to_string(), Box::new(Silent)).with_params(params);
+        Pipeline::new(&container, OutputFormat::Table).map_err(|_| container.errors())
+    }
+
+    #[test]
+    fn bind_params() {
+        let pipeline = compile(
+            "* | json | where host == $host | where n > $min | count",
+            &[("host", "web-1"), ("min", "5")],
+        );
+        assert!(pipeline.is_ok());
+
+        let errors = compile("* | json | where n > $min", &[("min", "five")])
+            .err()
+            .unwrap();
+        assert_eq!(
+            errors[0].message,
+            "Parameter $min is compared as a number, but its value `five` isn't a number"
+        );
+        assert_eq!(errors[0].annotations[0].0, 21..25);
+
+        let errors = compile(
+            "* | json | where host == $hostnam",
+            &[("hostname", "web-1")],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(errors[0].message, "No value for parameter $hostnam");
+        assert_eq!(errors[0].resolutions, vec!["Did you mean $hostname?"]);
+    }
+}
@@ -0,0 +1,142 @@
+//! Registry of user-defined inline operators and aggregate functions.  Once registered, a name
+//! can be used in queries like the built-in operators, either on its own or followed by a list
+//! of arguments, e.g. `* | my_format | my_agg(field) by other_field`.
+use crate::lang::{VALID_AGGREGATES, VALID_OPERATORS};
+use crate::operator::{AggregateFunction, Expr, OperatorBuilder};
+use failure::Error;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Creates an inline operator from the arguments given to it in the query.  An error is reported
+/// as a problem with the query.
+pub type InlineFactory =
+    Fn(&[Expr]) -> Result<Box<OperatorBuilder + Send + Sync>, Error> + Send + Sync;
+
+/// Creates an aggregate function from the arguments given to it in the query.  An error is
+/// reported as a problem with the query.
+pub type AggregateFactory = Fn(&[Expr]) -> Result<Box<AggregateFunction>, Error> + Send + Sync;
+
+#[derive(Debug, Fail)]
+pub enum RegistryError {
+    #[fail(display = "`{}` is not a valid operator name", name)]
+    InvalidName { name: String },
+
+    #[fail(display = "An operator named `{}` already exists", name)]
+    NameTaken { name: String },
+}
+
+#[derive(Default)]
+struct Registry {
+    inline: HashMap<&'static str, Arc<InlineFactory>>,
+    aggregates: HashMap<&'static str, Arc<AggregateFactory>>,
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<Registry> = RwLock::new(Registry::default());
+}
+
+impl Registry {
+    /// Check that the name can be parsed as an operator and isn't in use, then leak it since the
+    /// parser needs `'static` names to suggest.
+    fn claim(&self, name: &str) -> Result<&'static str, RegistryError> {
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid || name == "by" || name == "as" {
+            return Err(RegistryError::InvalidName {
+                name: name.to_string(),
+            });
+        }
+        if VALID_OPERATORS.contains(&name)
+            || self.inline.contains_key(name)
+            || self.aggregates.contains_key(name)
+        {
+            return Err(RegistryError::NameTaken {
+                name: name.to_string(),
+            });
+        }
+        Ok(Box::leak(name.to_string().into_boxed_str()))
+    }
+}
+
+/// Register an inline operator under the given name.
+pub fn register_inline<F>(name: &str, factory: F) -> Result<(), RegistryError>
+where
+    F: Fn(&[Expr]) -> Result<Box<OperatorBuilder + Send + Sync>, Error> + Send + Sync + 'static,
+{
+    let mut registry = REGISTRY.write().unwrap();
+    let name = registry.claim(name)?;
+    registry.inline.insert(name, Arc::new(factory));
+    Ok(())
+}
+
+/// Register an aggregate function under the given name.  The column it produces is named after
+/// the function with a leading underscore unless the query renames it.
+pub fn register_aggregate<F>(name: &str, factory: F) -> Result<(), RegistryError>
+where
+    F: Fn(&[Expr]) -> Result<Box<AggregateFunction>, Error> + Send + Sync + 'static,
+{
+    let mut registry = REGISTRY.write().unwrap();
+    let name = registry.claim(name)?;
+    registry.aggregates.insert(name, Arc::new(factory));
+    Ok(())
+}
+
+pub(crate) fn inline_factory(name: &str) -> Option<Arc<InlineFactory>> {
+    REGISTRY.read().unwrap().inline.get(name).cloned()
+}
+
+pub(crate) fn aggregate_factory(name: &str) -> Option<Arc<AggregateFactory>> {
+    REGISTRY.read().unwrap().aggregates.get(name).cloned()
+}
+
+/// The names of the registered inline operators.
+pub(crate) fn inline_names() -> Vec<&'static str> {
+    REGISTRY.read().unwrap().inline.keys().cloned().collect()
+}
+
+/// The names of the built-in and registered aggregate functions.
+pub(crate) fn valid_aggregates() -> Vec<&'static str> {
+    let registry = REGISTRY.read().unwrap();
+    VALID_AGGREGATES
+        .iter()
+        .chain(registry.aggregates.keys())
+        .cloned()
+        .collect()
+}
+
+/// The names of all of the built-in and registered operators.
+pub(crate) fn valid_operators() -> Vec<&'static str> {
+    let registry = REGISTRY.read().unwrap();
+    VALID_OPERATORS
+        .iter()
+        .chain(registry.inline.keys())
+        .chain(registry.aggregates.keys())
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::Count;
+
+    #[test]
+    fn register_names() {
+        assert!(register_aggregate("registry_test_agg", |_| Ok(Box::new(Count::new()))).is_ok());
+        assert!(valid_aggregates().contains(&"registry_test_agg"));
+        assert!(valid_operators().contains(&"registry_test_agg"));
+        match register_aggregate("registry_test_agg", |_| Ok(Box::new(Count::new()))) {
+            Err(RegistryError::NameTaken { .. }) => (),
+            other => panic!("expected NameTaken, got {:?}", other),
+        }
+        match register_aggregate("count", |_| Ok(Box::new(Count::new()))) {
+            Err(RegistryError::NameTaken { .. }) => (),
+            other => panic!("expected NameTaken, got {:?}", other),
+        }
+        match register_aggregate("1st", |_| Ok(Box::new(Count::new()))) {
+            Err(RegistryError::InvalidName { .. }) => (),
+            other => panic!("expected InvalidName, got {:?}", other),
+        }
+    }
+}
@@ -1,8 +1,13 @@
 use crate::data;
+use crate::pipeline::{RowSink, FILE_COLUMN};
+use ansi_term::{Colour, Style};
 use failure::Error;
+use regex::{Captures, Regex};
 use std;
 use std::collections::HashMap;
+use std::env;
 use std::io::{stdout, Write};
+use std::str::FromStr;
 
 extern crate terminal_size;
 
@@ -15,16 +20,124 @@ pub struct RenderConfig {
     pub max_buffer: usize,
 }
 
+/// The format that rows are written to stdout in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Padded `[key=value]` records and aligned aggregate tables, redrawn as they change.
+    Table,
+    /// One JSON object per record or aggregate row.
+    Json,
+    Csv,
+    Tsv,
+    /// One line of `key=value` pairs per record or aggregate row.
+    Logfmt,
+    /// Aggregates as a Markdown table, records as in `Table`.
+    Markdown,
+    /// Aggregates as an HTML table, records as in `Table`.
+    Html,
+}
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "Unknown output format `{}`, expected one of: table, json, csv, tsv, logfmt, markdown, html",
+    format
+)]
+pub struct UnknownOutputFormat {
+    format: String,
+}
+
+impl FromStr for OutputFormat {
+    type Err = UnknownOutputFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "logfmt" => Ok(OutputFormat::Logfmt),
+            "markdown" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            other => Err(UnknownOutputFormat {
+                format: other.to_string(),
+            }),
+        }
+    }
+}
+
+/// Turns rows into text.  Records are formatted as a single line without the trailing newline,
+/// aggregates as a block of complete lines.
+pub trait Formatter {
+    fn format_record(&mut self, record: &data::Record) -> String;
+    fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String;
+
+    /// Whether aggregates should be redrawn in place on a terminal as they change, rather than
+    /// only written once at the end.
+    fn live_update(&self) -> bool {
+        false
+    }
+}
+
 struct TerminalSize {
     height: u16,
     width: u16,
 }
 
+/// The styles used for the table output on a color terminal.  With color disabled, text is
+/// passed through unchanged.
+#[derive(Default)]
+struct Colors {
+    enabled: bool,
+    /// Finds the search keywords in raw lines.
+    keywords: Option<Regex>,
+}
+
+impl Colors {
+    fn paint(&self, style: Style, text: &str) -> String {
+        if self.enabled && !text.is_empty() {
+            style.paint(text).to_string()
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        self.paint(Style::new().dimmed(), key)
+    }
+
+    fn header(&self, header: &str) -> String {
+        self.paint(Style::new().bold(), header)
+    }
+
+    fn value(&self, value: &data::Value, rendered: &str) -> String {
+        let style = match *value {
+            data::Value::Str(_) => Colour::Green.normal(),
+            data::Value::Int(_) | data::Value::Float(_) => Colour::Cyan.normal(),
+            data::Value::Bool(_) => Colour::Yellow.normal(),
+            data::Value::None => Colour::Red.bold(),
+        };
+        self.paint(style, rendered)
+    }
+
+    /// Emphasize the search keywords in a line that wasn't parsed into fields.
+    fn raw(&self, line: &str) -> String {
+        match self.keywords {
+            Some(ref keywords) if self.enabled => keywords
+                .replace_all(line, |caps: &Captures| {
+                    self.paint(Colour::Yellow.bold(), &caps[0])
+                })
+                .into_owned(),
+            _ => line.to_string(),
+        }
+    }
+}
+
 struct PrettyPrinter {
     render_config: RenderConfig,
     column_widths: HashMap<String, usize>,
     column_order: Vec<String>,
     term_size: Option<TerminalSize>,
+    colors: Colors,
 }
 
 // MAYBE TODO: do any terminals not support unicode anymore? If so it would be nice to detect that
@@ -52,9 +165,15 @@ impl PrettyPrinter {
             term_size,
             column_widths: HashMap::new(),
             column_order: Vec::new(),
+            colors: Colors::default(),
         }
     }
 
+    fn with_colors(mut self, colors: Colors) -> Self {
+        self.colors = colors;
+        self
+    }
+
     fn compute_column_widths(&self, data: &HashMap<String, data::Value>) -> HashMap<String, usize> {
         data.iter()
             .map(|(column_name, value)| {
@@ -110,7 +229,7 @@ impl PrettyPrinter {
         let new_columns = self.new_columns(&(record.data));
         self.column_order.extend(new_columns);
         if self.column_order.is_empty() {
-            return record.raw.trim_end().to_string();
+            return self.colors.raw(record.raw.trim_end());
         }
 
         let no_padding = if self.overflows_term() {
@@ -128,20 +247,25 @@ impl PrettyPrinter {
             .map(|column_name| {
                 let value = record.data.get(column_name);
 
-                let unpadded = match value {
+                let (unpadded, colored) = match value {
                     Some(value) => {
-                        format!("[{}={}]", column_name, value.render(&self.render_config))
+                        let rendered = value.render(&self.render_config);
+                        let colored = format!(
+                            "{}{}{}",
+                            self.colors.key(&format!("[{}=", column_name)),
+                            self.colors.value(value, &rendered),
+                            self.colors.key("]")
+                        );
+                        (format!("[{}={}]", column_name, rendered), colored)
                     }
-                    None => "".to_string(),
+                    None => ("".to_string(), "".to_string()),
                 };
                 if no_padding {
-                    unpadded
+                    colored
                 } else {
-                    format!(
-                        "{:width$}",
-                        unpadded,
-                        width = column_name.len() + 3 + self.column_widths[column_name]
-                    )
+                    // The escape sequences don't take up any space, so pad by the plain text.
+                    let width = column_name.len() + 3 + self.column_widths[column_name];
+                    colored + &" ".repeat(width.saturating_sub(unpadded.chars().count()))
                 }
             })
             .collect();
@@ -199,12 +323,13 @@ impl PrettyPrinter {
         let row: Vec<String> = columns
             .iter()
             .map(|column_name| {
-                format_with_ellipsis(
-                    row.get(column_name)
-                        .unwrap_or(&data::Value::None)
-                        .render(&self.render_config),
+                let value = row.get(column_name).unwrap_or(&data::Value::None);
+                let cell = format_with_ellipsis(
+                    value.render(&self.render_config),
                     self.column_widths[column_name],
-                )
+                );
+                let content = cell.trim_end();
+                self.colors.value(value, content) + &cell[content.len()..]
             })
             .collect();
         row.join("").trim().to_string()
@@ -235,7 +360,11 @@ impl PrettyPrinter {
             .collect();
         let header = header.join("");
         let header_len = header.len();
-        let header = format!("{}\n{}", header.trim(), "-".repeat(header_len));
+        let header = format!(
+            "{}\n{}",
+            self.colors.header(header.trim()),
+            "-".repeat(header_len)
+        );
         let body: Vec<String> = aggregate
             .data
             .iter()
@@ -252,40 +381,374 @@ impl PrettyPrinter {
     }
 }
 
-pub struct Renderer {
+impl Formatter for PrettyPrinter {
+    fn format_record(&mut self, record: &data::Record) -> String {
+        PrettyPrinter::format_record(self, record)
+    }
+
+    fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String {
+        PrettyPrinter::format_aggregate(self, aggregate)
+    }
+
+    fn live_update(&self) -> bool {
+        true
+    }
+}
+
+/// The columns of a record, in the order they are written by the formats that don't have to
+/// line them up.
+fn record_columns(record: &data::Record) -> Vec<&String> {
+    let mut columns: Vec<&String> = record.data.keys().collect();
+    columns.sort();
+    columns
+}
+
+/// The value as written by the machine-readable formats: floats keep their full precision and
+/// missing values are left empty.
+fn plain_value(value: &data::Value) -> String {
+    match *value {
+        data::Value::None => "".to_string(),
+        ref other => other.to_string(),
+    }
+}
+
+/// Writes each record or aggregate row as a JSON object, keeping the columns in order.
+struct JsonFormatter;
+
+impl JsonFormatter {
+    fn to_json(value: &data::Value) -> serde_json::Value {
+        match *value {
+            data::Value::Str(ref s) => serde_json::Value::from(s.as_str()),
+            data::Value::Int(i) => serde_json::Value::from(i),
+            data::Value::Float(f) => serde_json::Value::from(f.into_inner()),
+            data::Value::Bool(b) => serde_json::Value::from(b),
+            data::Value::None => serde_json::Value::Null,
+        }
+    }
+
+    fn format_object<'a, I: Iterator<Item = (&'a String, &'a data::Value)>>(fields: I) -> String {
+        let fields: Vec<String> = fields
+            .map(|(key, value)| {
+                format!(
+                    "{}:{}",
+                    serde_json::Value::from(key.as_str()),
+                    JsonFormatter::to_json(value)
+                )
+            })
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+impl Formatter for JsonFormatter {
+    fn format_record(&mut self, record: &data::Record) -> String {
+        if record.data.is_empty() {
+            return serde_json::Value::from(record.raw.trim_end()).to_string();
+        }
+        JsonFormatter::format_object(
+            record_columns(record)
+                .into_iter()
+                .map(|column| (column, &record.data[column])),
+        )
+    }
+
+    fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String {
+        aggregate
+            .data
+            .iter()
+            .map(|row| {
+                let fields = aggregate
+                    .columns
+                    .iter()
+                    .map(|column| (column, row.get(column).unwrap_or(data::NONE)));
+                JsonFormatter::format_object(fields) + "\n"
+            })
+            .collect()
+    }
+}
+
+/// Writes rows as delimiter separated values with a header line.  The header of a record stream
+/// is written again whenever a record introduces a new column.
+struct DelimitedFormatter {
+    delimiter: char,
+    /// The columns of the first record, which are written as the header.  Columns that only
+    /// appear in later records are left out, so every line has the fields in the header.
+    columns: Vec<String>,
+}
+
+impl DelimitedFormatter {
+    fn new(delimiter: char) -> Self {
+        DelimitedFormatter {
+            delimiter,
+            columns: Vec::new(),
+        }
+    }
+
+    /// CSV fields are quoted when needed, TSV uses backslash escapes since it has no quoting.
+    fn escape(&self, field: &str) -> String {
+        if self.delimiter == '\t' {
+            field
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+        } else if field.contains(&[self.delimiter, '"', '\n', '\r'][..]) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    fn format_line<'a, I: Iterator<Item = &'a str>>(&self, fields: I) -> String {
+        let fields: Vec<String> = fields.map(|field| self.escape(field)).collect();
+        fields.join(&self.delimiter.to_string())
+    }
+}
+
+impl Formatter for DelimitedFormatter {
+    fn format_record(&mut self, record: &data::Record) -> String {
+        if record.data.is_empty() {
+            return self.escape(record.raw.trim_end());
+        }
+        let header = if self.columns.is_empty() {
+            self.columns = record_columns(record).into_iter().cloned().collect();
+            Some(self.format_line(self.columns.iter().map(String::as_str)))
+        } else {
+            None
+        };
+        let values: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| record.data.get(column).map(plain_value).unwrap_or_default())
+            .collect();
+        let line = self.format_line(values.iter().map(String::as_str));
+        match header {
+            Some(header) => format!("{}\n{}", header, line),
+            None => line,
+        }
+    }
+
+    fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String {
+        let mut output = self.format_line(aggregate.columns.iter().map(String::as_str)) + "\n";
+        for row in &aggregate.data {
+            let values: Vec<String> = aggregate
+                .columns
+                .iter()
+                .map(|column| row.get(column).map(plain_value).unwrap_or_default())
+                .collect();
+            output += &self.format_line(values.iter().map(String::as_str));
+            output += "\n";
+        }
+        output
+    }
+}
+
+/// Writes each record or aggregate row as a line of `key=value` pairs.
+struct LogfmtFormatter;
+
+impl LogfmtFormatter {
+    fn format_pair(key: &str, value: &data::Value) -> String {
+        let value = plain_value(value);
+        if value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+            format!(
+                "{}=\"{}\"",
+                key,
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            )
+        } else {
+            format!("{}={}", key, value)
+        }
+    }
+}
+
+impl Formatter for LogfmtFormatter {
+    fn format_record(&mut self, record: &data::Record) -> String {
+        if record.data.is_empty() {
+            return record.raw.trim_end().to_string();
+        }
+        let pairs: Vec<String> = record_columns(record)
+            .into_iter()
+            .map(|column| LogfmtFormatter::format_pair(column, &record.data[column]))
+            .collect();
+        pairs.join(" ")
+    }
+
+    fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String {
+        aggregate
+            .data
+            .iter()
+            .map(|row| {
+                let pairs: Vec<String> = aggregate
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        LogfmtFormatter::format_pair(column, row.get(column).unwrap_or(data::NONE))
+                    })
+                    .collect();
+                pairs.join(" ") + "\n"
+            })
+            .collect()
+    }
+}
+
+/// Writes aggregates as a Markdown table for pasting into documents.  Records are written the
+/// same way as on the terminal.
+struct MarkdownFormatter {
     pretty_printer: PrettyPrinter,
+}
+
+impl MarkdownFormatter {
+    fn escape(cell: &str) -> String {
+        cell.replace('\\', "\\\\")
+            .replace('|', "\\|")
+            .replace('\n', "<br>")
+    }
+
+    fn format_row<'a, I: Iterator<Item = &'a str>>(cells: I) -> String {
+        let cells: Vec<String> = cells.map(MarkdownFormatter::escape).collect();
+        format!("| {} |\n", cells.join(" | "))
+    }
+}
+
+impl Formatter for MarkdownFormatter {
+    fn format_record(&mut self, record: &data::Record) -> String {
+        self.pretty_printer.format_record(record)
+    }
+
+    fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String {
+        let config = &self.pretty_printer.render_config;
+        let mut output =
+            MarkdownFormatter::format_row(aggregate.columns.iter().map(String::as_str));
+        output += &MarkdownFormatter::format_row(aggregate.columns.iter().map(|_| "---"));
+        for row in &aggregate.data {
+            let values: Vec<String> = aggregate
+                .columns
+                .iter()
+                .map(|column| row.get(column).unwrap_or(data::NONE).render(config))
+                .collect();
+            output += &MarkdownFormatter::format_row(values.iter().map(String::as_str));
+        }
+        output
+    }
+}
+
+/// Writes aggregates as an HTML table.  Records are written the same way as on the terminal.
+struct HtmlFormatter {
+    pretty_printer: PrettyPrinter,
+}
+
+impl HtmlFormatter {
+    fn escape(cell: &str) -> String {
+        cell.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;")
+    }
+
+    fn format_row<'a, I: Iterator<Item = &'a str>>(tag: &str, cells: I) -> String {
+        let cells: Vec<String> = cells
+            .map(|cell| format!("<{tag}>{}</{tag}>", HtmlFormatter::escape(cell), tag = tag))
+            .collect();
+        format!("<tr>{}</tr>\n", cells.join(""))
+    }
+}
+
+impl Formatter for HtmlFormatter {
+    fn format_record(&mut self, record: &data::Record) -> String {
+        self.pretty_printer.format_record(record)
+    }
+
+    fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String {
+        let config = &self.pretty_printer.render_config;
+        let mut output = "<table>\n<thead>\n".to_string();
+        output += &HtmlFormatter::format_row("th", aggregate.columns.iter().map(String::as_str));
+        output += "</thead>\n<tbody>\n";
+        for row in &aggregate.data {
+            let values: Vec<String> = aggregate
+                .columns
+                .iter()
+                .map(|column| row.get(column).unwrap_or(data::NONE).render(config))
+                .collect();
+            output += &HtmlFormatter::format_row("td", values.iter().map(String::as_str));
+        }
+        output + "</tbody>\n</table>\n"
+    }
+}
+
+pub struct Renderer {
+    formatter: Box<Formatter + Send>,
     update_interval: Duration,
     stdout: std::io::Stdout,
 
     reset_sequence: String,
     is_tty: bool,
     last_print: Option<Instant>,
+    /// Whether to leave the `_file` column out of records.
+    hide_file: bool,
 }
 
 impl Renderer {
-    pub fn new(config: RenderConfig, update_interval: Duration) -> Self {
+    /// Create a renderer for stdout.  On a terminal, and unless `NO_COLOR` is set, the table
+    /// output is colored and matches of `highlight` in raw lines are emphasized.
+    pub fn new(
+        config: RenderConfig,
+        update_interval: Duration,
+        format: OutputFormat,
+        highlight: Option<Regex>,
+    ) -> Self {
         let tsize_opt =
             terminal_size().map(|(Width(width), Height(height))| TerminalSize { width, height });
+        let is_tty = tsize_opt.is_some();
+        let colors = Colors {
+            enabled: is_tty && env::var("NO_COLOR").is_err(),
+            keywords: highlight,
+        };
+        let formatter: Box<Formatter + Send> = match format {
+            OutputFormat::Table => {
+                Box::new(PrettyPrinter::new(config, tsize_opt).with_colors(colors))
+            }
+            OutputFormat::Json => Box::new(JsonFormatter),
+            OutputFormat::Csv => Box::new(DelimitedFormatter::new(',')),
+            OutputFormat::Tsv => Box::new(DelimitedFormatter::new('\t')),
+            OutputFormat::Logfmt => Box::new(LogfmtFormatter),
+            OutputFormat::Markdown => Box::new(MarkdownFormatter {
+                pretty_printer: PrettyPrinter::new(config, tsize_opt).with_colors(colors),
+            }),
+            OutputFormat::Html => Box::new(HtmlFormatter {
+                pretty_printer: PrettyPrinter::new(config, tsize_opt).with_colors(colors),
+            }),
+        };
         Renderer {
-            is_tty: tsize_opt.is_some(),
-            pretty_printer: PrettyPrinter::new(config, tsize_opt),
+            is_tty,
+            formatter,
             stdout: stdout(),
             reset_sequence: "".to_string(),
             last_print: None,
             update_interval,
+            hide_file: true,
         }
     }
 
+    /// Keep the `_file` column in the records, for a query that refers to it.
+    pub fn show_file_column(mut self) -> Self {
+        self.hide_file = false;
+        self
+    }
+
     pub fn render(&mut self, row: &data::Row, last_row: bool) -> Result<(), Error> {
         match *row {
             data::Row::Aggregate(ref aggregate) => {
-                if !self.is_tty {
+                if !self.is_tty || !self.formatter.live_update() {
                     if last_row {
-                        let output = self.pretty_printer.format_aggregate(aggregate);
+                        let output = self.formatter.format_aggregate(aggregate);
                         write!(self.stdout, "{}", output)?;
                     }
                 } else if self.should_print() || last_row {
-                    let output = self.pretty_printer.format_aggregate(aggregate);
+                    let output = self.formatter.format_aggregate(aggregate);
                     let num_lines = output.matches('\n').count();
                     write!(self.stdout, "{}{}", self.reset_sequence, output)?;
                     self.reset_sequence = "\x1b[2K\x1b[1A".repeat(num_lines);
@@ -295,7 +758,7 @@ impl Renderer {
                 Ok(())
             }
             data::Row::Record(ref record) => {
-                let output = self.pretty_printer.format_record(record);
+                let output = self.formatter.format_record(record);
                 writeln!(self.stdout, "{}", output)?;
 
                 Ok(())
@@ -304,7 +767,7 @@ impl Renderer {
     }
 
     pub fn should_print(&self) -> bool {
-        if !self.is_tty {
+        if !self.is_tty || !self.formatter.live_update() {
             return false;
         }
         self.last_print
@@ -313,12 +776,33 @@ impl Renderer {
     }
 }
 
+impl RowSink for Renderer {
+    fn record(&mut self, mut record: data::Record) -> Result<(), Error> {
+        // Unless the query asks for it, the file name would only get in the way of the line
+        // itself.
+        if self.hide_file {
+            record.data.remove(FILE_COLUMN);
+        }
+        self.render(&data::Row::Record(record), false)
+    }
+
+    fn aggregate(&mut self, aggregate: data::Aggregate, last: bool) -> Result<(), Error> {
+        self.render(&data::Row::Aggregate(aggregate), last)
+    }
+
+    fn wants_snapshot(&self) -> bool {
+        self.should_print()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::data::*;
+    use crate::lang::Keyword;
     use crate::operator::*;
     use maplit::hashmap;
+    use ordered_float::OrderedFloat;
 
     #[test]
     fn print_raw() {
@@ -334,6 +818,48 @@ mod tests {
         assert_eq!(pp.format_record(&rec), "Hello, World!");
     }
 
+    #[test]
+    fn colored_output() {
+        let colors = Colors {
+            enabled: true,
+            keywords: Keyword::any_regex(&[
+                Keyword::new_exact("error".to_string()),
+                Keyword::new_wildcard("tim*out".to_string()),
+            ]),
+        };
+        let mut pp = PrettyPrinter::new(
+            RenderConfig {
+                floating_points: 2,
+                min_buffer: 1,
+                max_buffer: 4,
+            },
+            None,
+        )
+        .with_colors(colors);
+        assert_eq!(
+            pp.format_record(&Record::new("ERROR: timed out\n")),
+            "\x1b[1;33mERROR\x1b[0m: \x1b[1;33mtimed out\x1b[0m"
+        );
+        let rec = Record::new("").put("k", Value::Int(5));
+        assert_eq!(
+            pp.format_record(&rec),
+            "\x1b[2m[k=\x1b[0m\x1b[36m5\x1b[0m\x1b[2m]\x1b[0m"
+        );
+        let agg = Aggregate {
+            columns: vec!["level".to_string(), "count".to_string()],
+            data: vec![hashmap! {
+                "level".to_string() => Value::None,
+                "count".to_string() => Value::Int(3),
+            }],
+        };
+        assert_eq!(
+            pp.format_aggregate(&agg),
+            "\x1b[1mlevel     count\x1b[0m\n\
+             -------------------\n\
+             \x1b[1;31m$None$\x1b[0m    \x1b[36m3\x1b[0m\n"
+        );
+    }
+
     #[test]
     fn pretty_print_record() {
         let rec = Record::new(r#"{"k1": 5, "k2": 5.5000001, "k3": "str"}"#);
@@ -488,6 +1014,118 @@ mod tests {
         );
     }
 
+    fn level_counts() -> Aggregate {
+        Aggregate::new(
+            &["level".to_string()],
+            "count".to_string(),
+            &[
+                (
+                    hashmap! {"level".to_string() => "info, mostly".to_string()},
+                    Value::Int(3),
+                ),
+                (
+                    hashmap! {"level".to_string() => "error".to_string()},
+                    Value::Float(OrderedFloat(1.5)),
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn json_aggregate() {
+        assert_eq!(
+            JsonFormatter.format_aggregate(&level_counts()),
+            "{\"level\":\"info, mostly\",\"count\":3}\n{\"level\":\"error\",\"count\":1.5}\n"
+        );
+    }
+
+    #[test]
+    fn delimited_aggregate() {
+        assert_eq!(
+            DelimitedFormatter::new(',').format_aggregate(&level_counts()),
+            "level,count\n\"info, mostly\",3\nerror,1.5\n"
+        );
+        assert_eq!(
+            DelimitedFormatter::new('\t').format_aggregate(&level_counts()),
+            "level\tcount\ninfo, mostly\t3\nerror\t1.5\n"
+        );
+    }
+
+    #[test]
+    fn delimited_record_new_columns() {
+        let parser = ParseJson::new(None);
+        let mut formatter = DelimitedFormatter::new(',');
+        let rec = parser.process(Record::new(r#"{"b": 1, "a": "x"}"#));
+        assert_eq!(formatter.format_record(&rec.unwrap().unwrap()), "a,b\nx,1");
+        let rec = parser.process(Record::new(r#"{"b": 2}"#));
+        assert_eq!(formatter.format_record(&rec.unwrap().unwrap()), ",2");
+        // Columns that weren't in the header are left out.
+        let rec = parser.process(Record::new(r#"{"a": "say \"hi\"", "c": 3}"#));
+        assert_eq!(
+            formatter.format_record(&rec.unwrap().unwrap()),
+            "\"say \"\"hi\"\"\","
+        );
+    }
+
+    #[test]
+    fn delimited_raw_lines() {
+        let mut formatter = DelimitedFormatter::new(',');
+        let rec = Record::new("a, \"b\"\n");
+        assert_eq!(formatter.format_record(&rec), "\"a, \"\"b\"\"\"");
+        let mut formatter = DelimitedFormatter::new('\t');
+        assert_eq!(formatter.format_record(&Record::new("a\tb")), "a\\tb");
+    }
+
+    #[test]
+    fn logfmt_aggregate() {
+        assert_eq!(
+            LogfmtFormatter.format_aggregate(&level_counts()),
+            "level=\"info, mostly\" count=3\nlevel=error count=1.5\n"
+        );
+    }
+
+    #[test]
+    fn markdown_aggregate() {
+        let mut formatter = MarkdownFormatter {
+            pretty_printer: PrettyPrinter::new(
+                RenderConfig {
+                    floating_points: 3,
+                    min_buffer: 1,
+                    max_buffer: 4,
+                },
+                None,
+            ),
+        };
+        let mut agg = level_counts();
+        agg.data[0].insert("level".to_string(), Value::from_string("a|b\\c"));
+        assert_eq!(
+            formatter.format_aggregate(&agg),
+            "| level | count |\n| --- | --- |\n| a\\|b\\\\c | 3 |\n| error | 1.500 |\n"
+        );
+    }
+
+    #[test]
+    fn html_aggregate() {
+        let mut formatter = HtmlFormatter {
+            pretty_printer: PrettyPrinter::new(
+                RenderConfig {
+                    floating_points: 2,
+                    min_buffer: 1,
+                    max_buffer: 4,
+                },
+                None,
+            ),
+        };
+        let mut agg = level_counts();
+        agg.data[0].insert("level".to_string(), Value::from_string("<b>\"R&D\"</b>"));
+        assert_eq!(
+            formatter.format_aggregate(&agg),
+            "<table>\n<thead>\n<tr><th>level</th><th>count</th></tr>\n</thead>\n<tbody>\n\
+             <tr><td>&lt;b&gt;&quot;R&amp;D&quot;&lt;/b&gt;</td><td>3</td></tr>\n\
+             <tr><td>error</td><td>1.50</td></tr>\n</tbody>\n</table>\n"
+        );
+    }
+
     #[test]
     fn test_format_with_ellipsis() {
         assert_eq!(format_with_ellipsis("abcde", 4), "ab… ");
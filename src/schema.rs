@@ -0,0 +1,494 @@
+//! Track the columns that each operator in a query produces, so references to columns that are
+//! never produced (usually typos) can be reported instead of silently evaluating to `None`.
+//!
+//! Most columns are known when the query is compiled: `parse` names its fields, `total` and the
+//! aggregates name their outputs.  Operators like `json` produce whatever columns are in the
+//! input, so references after them are checked against the first records instead.
+use crate::data::Record;
+use crate::errors::{did_you_mean, ErrorBuilder};
+use crate::lang::{
+    AggregateFunction, Expr, FieldMode, InlineOperator, Operator, Positioned, Query, QueryPosition,
+};
+use crate::pipeline::FILE_COLUMN;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// How many records to look at before warning about columns they don't contain.
+const CHECKED_RECORDS: usize = 1000;
+
+/// A reference to a column that no earlier operator produces.
+#[derive(Debug, PartialEq)]
+pub struct UnknownColumn {
+    pub column: String,
+    /// Where the column is referenced, if the syntax tree records it.
+    pub range: Option<(usize, usize)>,
+    /// The most similar column that is produced.
+    pub suggestion: Option<String>,
+}
+
+impl UnknownColumn {
+    pub fn report<T: ErrorBuilder>(&self, error_builder: &T) {
+        let mut builder = error_builder.report_error_for(format!(
+            "Column `{}` isn't produced by any earlier operator",
+            self.column
+        ));
+        if let Some((start, end)) = self.range {
+            builder = builder.with_code_range(
+                QueryPosition(start),
+                QueryPosition(end),
+                "Referenced here",
+            );
+        }
+        if let Some(ref suggestion) = self.suggestion {
+            builder = builder.with_resolution(format!("Did you mean `{}`?", suggestion));
+        }
+        builder.send_warning();
+    }
+}
+
+/// The result of checking the column references in a query.
+#[derive(Debug, Default, PartialEq)]
+pub struct Check {
+    /// References to columns that are definitely never produced.
+    pub unknown: Vec<UnknownColumn>,
+    /// References that can't be checked until the input is seen, because an earlier operator
+    /// produces columns that depend on it.
+    pub unresolved: Vec<String>,
+    /// The columns each operator in the query uses and produces, in order.
+    pub operators: Vec<Columns>,
+}
+
+/// The columns an operator uses and the columns that are present after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// Whether there may also be columns from the input, like the fields found by `json`.
+    pub open: bool,
+}
+
+/// The columns that may be present at some point in the query.
+struct Schema {
+    /// The columns in the order they're added.
+    columns: Vec<String>,
+    /// Whether the `_file` column may be present, which it is until the first aggregate when
+    /// reading files.
+    file_column: bool,
+    /// Set when an earlier operator can produce columns that aren't known in advance.
+    open: bool,
+    /// Set once the first aggregate has been passed.  Only references before it can be checked
+    /// against the input.
+    aggregated: bool,
+    /// The columns referenced by the current operator.
+    inputs: Vec<String>,
+    check: Check,
+}
+
+impl Schema {
+    fn reference(&mut self, column: &str, range: Option<(usize, usize)>) {
+        if !self.inputs.iter().any(|input| input == column) {
+            self.inputs.push(column.to_string());
+        }
+        if self.columns.iter().any(|c| c == column) {
+            return;
+        }
+        // `_file` is only set when reading files, so whether it's there depends on the input.
+        let maybe_file = self.file_column && column == FILE_COLUMN;
+        if !self.open && !maybe_file {
+            let choices: Vec<&str> = self.columns.iter().map(String::as_str).collect();
+            self.check.unknown.push(UnknownColumn {
+                column: column.to_string(),
+                range,
+                suggestion: did_you_mean(column, &choices),
+            });
+        } else if !self.aggregated && !self.check.unresolved.iter().any(|c| c == column) {
+            self.check.unresolved.push(column.to_string());
+        }
+    }
+
+    fn reference_expr(&mut self, expr: &Expr, range: Option<(usize, usize)>) {
+        match *expr {
+            Expr::Column(ref column) => self.reference(column, range),
+            Expr::Unary { ref operand, .. } => self.reference_expr(operand, range),
+            Expr::Binary {
+                ref left,
+                ref right,
+                ..
+            } => {
+                self.reference_expr(left, range);
+                self.reference_expr(right, range);
+            }
+            Expr::Value(_) | Expr::Param(_) => (),
+        }
+    }
+
+    /// Only the given columns are present from now on.
+    fn close(&mut self, columns: impl IntoIterator<Item = String>) {
+        self.columns.clear();
+        for column in columns {
+            self.add(column);
+        }
+        self.file_column = false;
+        self.open = false;
+    }
+
+    fn add(&mut self, column: String) {
+        if !self.columns.contains(&column) {
+            self.columns.push(column);
+        }
+    }
+
+    /// Record the columns used and produced by the operator that was just checked.
+    fn finish_operator(&mut self) {
+        let columns = Columns {
+            inputs: self.inputs.drain(..).collect(),
+            outputs: self.columns.clone(),
+            open: self.open,
+        };
+        self.check.operators.push(columns);
+    }
+
+    fn inline(&mut self, op: &Positioned<InlineOperator>) {
+        let range = Some(range(op));
+        match op.value {
+            InlineOperator::Json { ref input_column } => {
+                if let Some(ref column) = *input_column {
+                    self.reference(column, range);
+                }
+                self.open = true;
+            }
+            InlineOperator::Parse {
+                ref fields,
+                ref input_column,
+                ..
+            } => {
+                if let Some(ref expr) = *input_column {
+                    self.reference_expr(expr, range);
+                }
+                for field in fields {
+                    self.add(field.clone());
+                }
+            }
+            InlineOperator::Fields {
+                mode: FieldMode::Only,
+                ref fields,
+            } => {
+                for field in fields {
+                    self.reference(field, range);
+                }
+                self.close(fields.iter().cloned());
+            }
+            InlineOperator::Fields {
+                mode: FieldMode::Except,
+                ref fields,
+            } => {
+                self.columns.retain(|column| !fields.contains(column));
+            }
+            InlineOperator::Where { ref expr } => {
+                if let Some(ref expr) = *expr {
+                    self.reference_expr(&expr.value, Some(self::range(expr)));
+                }
+            }
+            InlineOperator::Limit { .. } => (),
+            InlineOperator::Total {
+                ref input_column,
+                ref output_column,
+            } => {
+                self.reference_expr(input_column, range);
+                self.add(output_column.clone());
+            }
+            InlineOperator::Rename { ref columns } => {
+                for (old, new) in columns {
+                    self.reference(old, range);
+                    match self.columns.iter().position(|column| column == old) {
+                        Some(_) if self.columns.contains(new) => {
+                            self.columns.retain(|column| column != old)
+                        }
+                        Some(index) => self.columns[index] = new.clone(),
+                        None => self.add(new.clone()),
+                    }
+                }
+            }
+            InlineOperator::FillNull { ref columns, .. } => {
+                for column in columns {
+                    self.add(column.clone());
+                }
+            }
+            InlineOperator::Split {
+                ref column,
+                ref output_column,
+                ..
+            } => {
+                self.reference(column, range);
+                if let Some(ref output_column) = *output_column {
+                    self.add(output_column.clone());
+                }
+            }
+            InlineOperator::Dedup { ref key_cols, .. } => {
+                for expr in key_cols {
+                    self.reference_expr(expr, range);
+                }
+            }
+            // The arguments of operators from the registry don't have to be columns.
+            InlineOperator::Custom { .. } => self.open = true,
+        }
+    }
+}
+
+fn range<T>(positioned: &Positioned<T>) -> (usize, usize) {
+    (positioned.start_pos.0, positioned.end_pos.0)
+}
+
+/// Check the column references in the query against the columns produced by the operators
+/// before them.
+pub fn check(query: &Query) -> Check {
+    let mut schema = Schema {
+        columns: Vec::new(),
+        file_column: true,
+        open: false,
+        aggregated: false,
+        inputs: Vec::new(),
+        check: Check::default(),
+    };
+    for op in &query.operators {
+        match *op {
+            Operator::Inline(ref op) => schema.inline(op),
+            Operator::MultiAggregate(ref agg) => {
+                for key in &agg.key_cols {
+                    schema.reference_expr(key, None);
+                }
+                for (_, function) in &agg.aggregate_functions {
+                    let range = Some(range(function));
+                    match function.value {
+                        AggregateFunction::Count | AggregateFunction::Custom { .. } => (),
+                        AggregateFunction::Sum { ref column }
+                        | AggregateFunction::Average { ref column }
+                        | AggregateFunction::Percentile { ref column, .. } => {
+                            schema.reference_expr(column, range)
+                        }
+                        AggregateFunction::CountDistinct { ref column } => {
+                            for expr in column.iter().flat_map(|column| &column.value) {
+                                schema.reference_expr(expr, range);
+                            }
+                        }
+                    }
+                }
+                schema.aggregated = true;
+                schema.close(
+                    agg.key_col_headers
+                        .iter()
+                        .cloned()
+                        .chain(agg.aggregate_functions.iter().map(|(name, _)| name.clone())),
+                );
+            }
+            Operator::LogReduce(ref op) => {
+                if let Some(ref column) = op.value.input_column {
+                    schema.reference_expr(column, None);
+                }
+                schema.aggregated = true;
+                schema.close(
+                    ["_template", "_count", "_example"]
+                        .iter()
+                        .map(|column| column.to_string()),
+                );
+            }
+            Operator::Sort(ref sort) => {
+                for column in &sort.sort_cols {
+                    schema.reference(column, None);
+                }
+            }
+            Operator::Chart(ref chart) => {
+                if let Some(ref column) = chart.column {
+                    schema.reference(column, None);
+                }
+                schema.add("_chart".to_string());
+            }
+            // Nothing is known about what the macro would expand to.
+            Operator::Macro(_) => schema.open = true,
+        }
+        schema.finish_operator();
+    }
+    schema.check
+}
+
+/// Checks the columns that couldn't be checked when the query was compiled against the columns
+/// in the first records.
+#[derive(Default)]
+pub struct ColumnCheck {
+    columns: Vec<String>,
+    /// The columns seen so far and the number of records checked.
+    seen: Mutex<(BTreeSet<String>, usize)>,
+    done: AtomicBool,
+}
+
+impl ColumnCheck {
+    pub fn new(columns: Vec<String>) -> Self {
+        ColumnCheck {
+            done: AtomicBool::new(columns.is_empty()),
+            columns,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// Whether records still need to be checked.
+    pub fn active(&self) -> bool {
+        !self.done.load(Ordering::Relaxed)
+    }
+
+    /// Note the columns in a record that is still being processed.
+    pub fn observe(&self, record: &Record) {
+        self.seen
+            .lock()
+            .unwrap()
+            .0
+            .extend(record.data.keys().cloned());
+    }
+
+    /// Note the columns in a record that made it through the operators.  Returns the warnings
+    /// once enough records have been checked.
+    pub fn checked(&self, record: &Record) -> Vec<String> {
+        let mut seen = self.seen.lock().unwrap();
+        seen.0.extend(record.data.keys().cloned());
+        seen.1 += 1;
+        if seen.1 < CHECKED_RECORDS || self.done.swap(true, Ordering::Relaxed) {
+            return Vec::new();
+        }
+        self.warnings(&seen.0, seen.1)
+    }
+
+    /// Returns the warnings if the input ended before enough records were checked.
+    pub fn finish(&self) -> Vec<String> {
+        let seen = self.seen.lock().unwrap();
+        if seen.1 == 0 || self.done.swap(true, Ordering::Relaxed) {
+            return Vec::new();
+        }
+        self.warnings(&seen.0, seen.1)
+    }
+
+    fn warnings(&self, seen: &BTreeSet<String>, records: usize) -> Vec<String> {
+        let choices: Vec<&str> = seen.iter().map(String::as_str).collect();
+        self.columns
+            .iter()
+            .filter(|column| !seen.contains(*column))
+            .map(|column| {
+                let records = if records == 1 {
+                    "the first record".to_string()
+                } else {
+                    format!("any of the first {} records", records)
+                };
+                match did_you_mean(column, &choices) {
+                    Some(suggestion) => format!(
+                        "Column `{}` wasn't in {} (did you mean `{}`?)",
+                        column, records, suggestion
+                    ),
+                    None => format!("Column `{}` wasn't in {}", column, records),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+    use crate::pipeline::parse_query;
+
+    fn check_query(query: &str) -> Check {
+        check(&parse_query(query).unwrap())
+    }
+
+    #[test]
+    fn unknown_columns() {
+        let check = check_query(r#"* | parse "* *" as status, url | count by stauts"#);
+        assert_eq!(
+            check.unknown,
+            vec![UnknownColumn {
+                column: "stauts".to_string(),
+                range: None,
+                suggestion: Some("status".to_string()),
+            }]
+        );
+        assert!(check.unresolved.is_empty());
+
+        let query = r#"* | parse "*" as x | total(x) as total | where totl > 5 | count by _file"#;
+        let check = check_query(query);
+        assert_eq!(check.unknown.len(), 1);
+        assert_eq!(check.unknown[0].suggestion, Some("total".to_string()));
+        let (start, end) = check.unknown[0].range.unwrap();
+        assert_eq!(query[start..end].trim_end(), "totl > 5");
+
+        let query = r#"* | parse "* *" as a, b | fields except a | count by a | sort by _cont"#;
+        let unknown: Vec<_> = check_query(query)
+            .unknown
+            .into_iter()
+            .map(|unknown| (unknown.column, unknown.suggestion))
+            .collect();
+        assert_eq!(
+            unknown,
+            vec![
+                ("a".to_string(), None),
+                ("_cont".to_string(), Some("_count".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn unresolved_columns() {
+        let check = check_query("* | json | where stauts == 5 | count by level | sort by lvl");
+        assert_eq!(check.unresolved, vec!["stauts", "level"]);
+        assert_eq!(check.unknown.len(), 1);
+        assert_eq!(check.unknown[0].column, "lvl");
+    }
+
+    #[test]
+    fn operator_columns() {
+        let check = check_query(
+            r#"* | json | parse "* *" from msg as a, b | fields except b | sum(n) by a, _file"#,
+        );
+        let columns = |inputs: &[&str], outputs: &[&str], open| Columns {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            open,
+        };
+        assert_eq!(
+            check.operators,
+            vec![
+                columns(&[], &[], true),
+                columns(&["msg"], &["a", "b"], true),
+                columns(&[], &["a"], true),
+                columns(&["a", "_file", "n"], &["a", "_file", "_sum"], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn column_check() {
+        let check = ColumnCheck::new(vec!["stauts".to_string(), "level".to_string()]);
+        assert!(check.active());
+        let record = Record::new("")
+            .put("status", Value::Int(200))
+            .put("level", Value::Str("info".to_string()));
+        check.observe(&record);
+        for _ in 0..CHECKED_RECORDS - 1 {
+            assert_eq!(check.checked(&record), Vec::<String>::new());
+        }
+        assert_eq!(
+            check.checked(&record),
+            vec![
+                "Column `stauts` wasn't in any of the first 1000 records (did you mean `status`?)"
+            ]
+        );
+        assert!(!check.active());
+        assert_eq!(check.finish(), Vec::<String>::new());
+
+        let check = ColumnCheck::new(vec!["x".to_string()]);
+        assert_eq!(check.finish(), Vec::<String>::new());
+        check.checked(&record);
+        assert_eq!(
+            check.finish(),
+            vec!["Column `x` wasn't in the first record"]
+        );
+    }
+}
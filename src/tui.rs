@@ -0,0 +1,475 @@
+//! Full-screen interactive view of a live aggregate.  The view can be scrolled, re-sorted by any
+//! column and paused, and stays open once the input ends until it is closed.
+use crate::data::{Aggregate, Record};
+use crate::pipeline::{RowSink, Stats};
+use crate::render::RenderConfig;
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use failure::Error;
+use std::io::{self, Write};
+use std::iter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the aggregate is sent to the view and the view checks for input.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Returned by the sink when the view was closed before the input ended, to stop processing.
+#[derive(Debug, Fail)]
+#[fail(display = "The interactive view was closed")]
+pub struct Closed;
+
+/// A `RowSink` that shows the aggregate in the interactive view, which runs on its own thread so
+/// it stays responsive when the input stalls.
+pub struct Interactive {
+    tx: Option<Sender<Aggregate>>,
+    ui: Option<JoinHandle<io::Result<()>>>,
+    /// Set by the view's thread when it's closed, or fails, before the input ends.
+    closed: Arc<AtomicBool>,
+    last_snapshot: Option<Instant>,
+}
+
+impl Interactive {
+    pub fn new(stats: Arc<Stats>) -> Self {
+        let (tx, rx) = unbounded();
+        let closed = Arc::new(AtomicBool::new(false));
+        let ui_closed = closed.clone();
+        let ui = thread::spawn(move || {
+            let result = run(&rx, View::new(stats));
+            if result.is_err() {
+                let _ = restore_terminal();
+            }
+            ui_closed.store(true, Ordering::SeqCst);
+            result
+        });
+        Interactive {
+            tx: Some(tx),
+            ui: Some(ui),
+            closed,
+            last_snapshot: None,
+        }
+    }
+
+    /// Closing the channel tells the view that the input has ended; keep the results on screen
+    /// until the view is closed.
+    fn finish(&mut self) -> Result<(), Error> {
+        self.tx.take();
+        match self.ui.take().map(JoinHandle::join) {
+            Some(Ok(Err(e))) => Err(e.into()),
+            // The panic has already been printed.
+            Some(Err(_)) => Err(format_err!("The interactive view failed")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Processing can stop without a final aggregate, but the terminal still has to be restored.
+impl Drop for Interactive {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl RowSink for Interactive {
+    fn record(&mut self, _record: Record) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn aggregate(&mut self, aggregate: Aggregate, last: bool) -> Result<(), Error> {
+        self.last_snapshot = Some(Instant::now());
+        if self.closed.load(Ordering::SeqCst) {
+            self.finish()?;
+            return Err(Closed.into());
+        }
+        if let Some(ref tx) = self.tx {
+            // The view only goes away once it's closed, which is checked above.
+            let _ = tx.send(aggregate);
+        }
+        if last {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    /// A closed view asks for a snapshot straight away, so processing stops.
+    fn wants_snapshot(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+            || self
+                .last_snapshot
+                .is_none_or(|instant| instant.elapsed() > REFRESH_INTERVAL)
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    execute!(io::stdout(), Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()
+}
+
+/// Draw the view until it is closed, which can be before the input has ended.
+fn run(rx: &Receiver<Aggregate>, mut view: View) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+    loop {
+        loop {
+            match rx.try_recv() {
+                Ok(aggregate) => view.update(aggregate),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    view.done = true;
+                    break;
+                }
+            }
+        }
+
+        let (width, height) = terminal::size()?;
+        queue!(stdout, MoveTo(0, 0), Clear(ClearType::All))?;
+        let lines = view.lines(width as usize, height as usize);
+        for (i, line) in lines.iter().enumerate() {
+            queue!(stdout, MoveTo(0, i as u16))?;
+            // The header and status line are highlighted.
+            if i == 0 || i == lines.len() - 1 {
+                queue!(
+                    stdout,
+                    SetAttribute(Attribute::Reverse),
+                    Print(line),
+                    SetAttribute(Attribute::Reset)
+                )?;
+            } else {
+                queue!(stdout, Print(line))?;
+            }
+        }
+        stdout.flush()?;
+
+        if event::poll(REFRESH_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Release && !view.handle_key(key, height as usize) {
+                    return restore_terminal();
+                }
+            }
+        }
+    }
+}
+
+/// The state of the interactive view.
+struct View {
+    aggregate: Aggregate,
+    /// The latest aggregate received while the view was paused.
+    pending: Option<Aggregate>,
+    /// The index of the column to sort by and whether to sort in descending order.
+    sort: Option<(usize, bool)>,
+    /// The index of the selected column, which `s` sorts by.
+    cursor: usize,
+    scroll: usize,
+    paused: bool,
+    done: bool,
+    stats: Arc<Stats>,
+    render_config: RenderConfig,
+}
+
+impl View {
+    fn new(stats: Arc<Stats>) -> Self {
+        View {
+            aggregate: Aggregate {
+                columns: Vec::new(),
+                data: Vec::new(),
+            },
+            pending: None,
+            sort: None,
+            cursor: 0,
+            scroll: 0,
+            paused: false,
+            done: false,
+            stats,
+            render_config: RenderConfig {
+                floating_points: 2,
+                min_buffer: 2,
+                max_buffer: 2,
+            },
+        }
+    }
+
+    fn update(&mut self, aggregate: Aggregate) {
+        if self.paused {
+            self.pending = Some(aggregate);
+        } else {
+            self.aggregate = aggregate;
+            self.sort_rows();
+        }
+    }
+
+    fn sort_rows(&mut self) {
+        if let Some((index, descending)) = self.sort {
+            if let Some(column) = self.aggregate.columns.get(index) {
+                let ordering = Record::ordering(vec![column.clone()]);
+                if descending {
+                    self.aggregate.data.sort_by(|l, r| ordering(r, l));
+                } else {
+                    self.aggregate.data.sort_by(|l, r| ordering(l, r));
+                }
+            }
+        }
+    }
+
+    /// Sort by the given column, or reverse the order if already sorted by it.
+    fn sort_by(&mut self, index: usize) {
+        if index >= self.aggregate.columns.len() {
+            return;
+        }
+        self.sort = match self.sort {
+            Some((current, descending)) if current == index => Some((index, !descending)),
+            _ => Some((index, true)),
+        };
+        self.sort_rows();
+    }
+
+    /// Handle a keypress.  Returns false if the view should be closed.
+    fn handle_key(&mut self, key: KeyEvent, height: usize) -> bool {
+        let page = height.saturating_sub(2).max(1);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll += 1,
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(page),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll += page,
+            KeyCode::Home | KeyCode::Char('g') => self.scroll = 0,
+            KeyCode::End | KeyCode::Char('G') => self.scroll = self.aggregate.data.len(),
+            KeyCode::Char('p') => {
+                self.paused = !self.paused;
+                if let Some(aggregate) = self.pending.take() {
+                    self.update(aggregate);
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.cursor = (self.cursor + 1).min(self.aggregate.columns.len().saturating_sub(1))
+            }
+            KeyCode::Char('s') => self.sort_by(self.cursor),
+            KeyCode::Char(digit @ '1'..='9') => self.sort_by(digit as usize - '1' as usize),
+            _ => (),
+        }
+        true
+    }
+
+    /// The lines to draw: a header, as many rows as fit and a status line.
+    fn lines(&mut self, width: usize, height: usize) -> Vec<String> {
+        let rows = height.saturating_sub(2);
+        self.scroll = self
+            .scroll
+            .min(self.aggregate.data.len().saturating_sub(rows));
+        let headers: Vec<String> = self
+            .aggregate
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let header = match self.sort {
+                    Some((index, true)) if index == i => format!("{} ▼", column),
+                    Some((index, false)) if index == i => format!("{} ▲", column),
+                    _ => column.clone(),
+                };
+                if i == self.cursor {
+                    format!("[{}]", header)
+                } else {
+                    header
+                }
+            })
+            .collect();
+        let cells: Vec<Vec<String>> = self
+            .aggregate
+            .data
+            .iter()
+            .skip(self.scroll)
+            .take(rows)
+            .map(|row| {
+                self.aggregate
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        row.get(column)
+                            .map(|value| value.render(&self.render_config))
+                            .unwrap_or_default()
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<usize> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain(iter::once(header.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+                    + self.render_config.min_buffer
+            })
+            .collect();
+        let format_row = |row: &[String]| -> String {
+            let line: String = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            fit(&line, width)
+        };
+
+        let mut lines = vec![format_row(&headers)];
+        lines.extend(cells.iter().map(|row| format_row(row)));
+        while lines.len() < rows + 1 {
+            lines.push(String::new());
+        }
+        lines.push(fit(&self.status(rows), width));
+        lines
+    }
+
+    fn status(&self, rows: usize) -> String {
+        let total = self.aggregate.data.len();
+        let state = if self.done {
+            "done"
+        } else if self.paused {
+            "paused"
+        } else {
+            "live"
+        };
+        format!(
+            "{} lines read, {} matched, {} errors | rows {}-{} of {} | {} | ←→ column, s sort, p pause, q quit",
+            self.stats.lines_read(),
+            self.stats.lines_matched(),
+            self.stats.errors(),
+            (self.scroll + 1).min(total),
+            (self.scroll + rows).min(total),
+            total,
+            state
+        )
+    }
+}
+
+/// Pad or truncate the line to exactly `width` characters.
+fn fit(line: &str, width: usize) -> String {
+    format!("{:width$.width$}", line, width = width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+    use maplit::hashmap;
+
+    fn view() -> View {
+        let mut view = View::new(Arc::new(Stats::default()));
+        view.update(Aggregate::new(
+            &["level".to_string()],
+            "_count".to_string(),
+            &[
+                (
+                    hashmap! {"level".to_string() => "info".to_string()},
+                    Value::Int(3),
+                ),
+                (
+                    hashmap! {"level".to_string() => "error".to_string()},
+                    Value::Int(20),
+                ),
+                (
+                    hashmap! {"level".to_string() => "warn".to_string()},
+                    Value::Int(7),
+                ),
+            ],
+        ));
+        view
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn sort_and_scroll() {
+        let mut view = view();
+        assert!(view.handle_key(key(KeyCode::Char('2')), 4));
+        assert_eq!(
+            view.lines(40, 4),
+            vec![
+                "[level]  _count ▼                       ",
+                "error    20                             ",
+                "warn     7                              ",
+                "0 lines read, 0 matched, 0 errors | rows",
+            ]
+        );
+        view.handle_key(key(KeyCode::Char('2')), 4);
+        view.handle_key(key(KeyCode::Down), 4);
+        view.handle_key(key(KeyCode::Down), 4);
+        let lines = view.lines(80, 4);
+        assert_eq!(lines[0].trim_end(), "[level]  _count ▲");
+        assert_eq!(lines[1].trim_end(), "warn     7");
+        assert_eq!(lines[2].trim_end(), "error    20");
+        assert!(lines[3].contains("rows 2-3 of 3 | live"));
+        assert!(!view.handle_key(key(KeyCode::Char('q')), 4));
+    }
+
+    #[test]
+    fn cursor_sort() {
+        let mut view = view();
+        view.handle_key(key(KeyCode::Left), 10);
+        assert_eq!(view.cursor, 0);
+        view.handle_key(key(KeyCode::Char('s')), 10);
+        assert_eq!(view.sort, Some((0, true)));
+        let levels: Vec<_> = view.lines(80, 10)[1..4]
+            .iter()
+            .map(|line| line.split_whitespace().next().unwrap().to_string())
+            .collect();
+        assert_eq!(levels, vec!["warn", "info", "error"]);
+
+        view.handle_key(key(KeyCode::Right), 10);
+        view.handle_key(key(KeyCode::Right), 10);
+        assert_eq!(view.cursor, 1);
+        view.handle_key(key(KeyCode::Char('s')), 10);
+        view.handle_key(key(KeyCode::Char('s')), 10);
+        assert_eq!(view.sort, Some((1, false)));
+        let lines = view.lines(80, 10);
+        assert_eq!(lines[0].trim_end(), "level  [_count ▲]");
+        assert_eq!(lines[1].trim_end(), "info   3");
+        assert_eq!(lines[3].trim_end(), "error  20");
+    }
+
+    #[test]
+    fn scroll_bounds() {
+        let mut view = view();
+        // Two rows fit, so the view can't scroll past the second of three.
+        view.handle_key(key(KeyCode::End), 4);
+        assert!(view.lines(80, 4)[3].contains("rows 2-3 of 3"));
+        view.handle_key(key(KeyCode::PageUp), 4);
+        assert_eq!(view.scroll, 0);
+        view.handle_key(key(KeyCode::PageDown), 4);
+        assert!(view.lines(80, 4)[3].contains("rows 2-3 of 3"));
+        view.handle_key(key(KeyCode::Home), 4);
+        view.handle_key(key(KeyCode::Up), 4);
+        assert!(view.lines(80, 4)[3].contains("rows 1-2 of 3"));
+        // Sorting keeps the scroll position.
+        view.handle_key(key(KeyCode::Down), 4);
+        view.handle_key(key(KeyCode::Char('s')), 4);
+        assert_eq!(view.scroll, 1);
+    }
+
+    #[test]
+    fn pause() {
+        let mut view = view();
+        view.handle_key(key(KeyCode::Char('p')), 10);
+        view.update(Aggregate {
+            columns: vec!["level".to_string()],
+            data: Vec::new(),
+        });
+        assert_eq!(view.aggregate.data.len(), 3);
+        assert!(view.lines(100, 10)[9].contains("paused"));
+        view.handle_key(key(KeyCode::Char('p')), 10);
+        assert!(view.aggregate.data.is_empty());
+    }
+}
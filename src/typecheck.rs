@@ -2,6 +2,7 @@ use crate::data::Value;
 use crate::errors::ErrorBuilder;
 use crate::lang;
 use crate::operator;
+use crate::registry;
 
 #[derive(Debug, Fail)]
 pub enum TypeError {
@@ -19,6 +20,24 @@ pub enum TypeError {
 
     #[fail(display = "Limit must be a non-zero integer, found {}", limit)]
     InvalidLimit { limit: f64 },
+
+    #[fail(
+        display = "Dedup window must hold at least one record, found {}",
+        within
+    )]
+    InvalidWindow { within: usize },
+
+    #[fail(display = "Invalid arguments for {}: {}", name, message)]
+    InvalidArguments { name: String, message: String },
+
+    #[fail(display = "No operator named {} is registered", name)]
+    UnregisteredOperator { name: String },
+
+    #[fail(display = "Split needs a non-empty separator")]
+    EmptySeparator,
+
+    #[fail(display = "logreduce after an aggregate needs a column to group")]
+    LogReduceWithoutColumn,
 }
 
 impl From<lang::ComparisonOp> for operator::BoolExpr {
@@ -58,11 +77,12 @@ impl From<lang::Expr> for operator::Expr {
                 let static_value: &'static mut Value = Box::leak(boxed);
                 operator::Expr::Value(static_value)
             }
+            lang::Expr::Param(_) => unreachable!("Parameters are bound when the query is parsed"),
         }
     }
 }
 
-const DEFAULT_LIMIT: i64 = 10;
+pub(crate) const DEFAULT_LIMIT: i64 = 10;
 
 impl lang::Positioned<lang::InlineOperator> {
     /// Convert the operator syntax to a builder that can instantiate an operator for the
@@ -181,6 +201,96 @@ impl lang::Positioned<lang::InlineOperator> {
                 input_column.into(),
                 output_column,
             ))),
+            lang::InlineOperator::Rename { columns } => {
+                Ok(Box::new(operator::Rename::new(columns)))
+            }
+            lang::InlineOperator::FillNull { value, columns } => {
+                let value = match value {
+                    lang::Expr::Value(value) => value,
+                    _ => unreachable!("fillnull only parses values"),
+                };
+                Ok(Box::new(operator::FillNull::new(value, columns)))
+            }
+            lang::InlineOperator::Split {
+                column,
+                separator,
+                output_column,
+            } => {
+                if let Some(ref separator) = separator {
+                    if separator.value.is_empty() {
+                        let e = TypeError::EmptySeparator;
+
+                        error_builder
+                            .report_error_for(e.to_string())
+                            .with_code_pointer(separator, "Empty separator")
+                            .with_resolution("Leave out `on` to split on commas or JSON arrays")
+                            .send_report();
+
+                        return Err(e);
+                    }
+                }
+                let output_column = output_column.unwrap_or_else(|| column.clone());
+                Ok(Box::new(operator::Split::new(
+                    column,
+                    separator.map(|separator| separator.value),
+                    output_column,
+                )))
+            }
+            lang::InlineOperator::Dedup {
+                key_cols,
+                keep,
+                within,
+            } => {
+                let within = match within {
+                    Some(ref within) if within.value == 0 => {
+                        let e = TypeError::InvalidWindow {
+                            within: within.value,
+                        };
+
+                        error_builder
+                            .report_error_for(e.to_string())
+                            .with_code_pointer(within, "Zero is not allowed")
+                            .with_resolution("Use a positive integer to only drop duplicates that are at most N records apart")
+                            .send_report();
+
+                        return Err(e);
+                    }
+                    Some(within) => Some(within.value),
+                    None => None,
+                };
+                Ok(Box::new(operator::DedupDef::new(
+                    key_cols.into_iter().map(Into::into).collect(),
+                    keep,
+                    within,
+                )))
+            }
+            lang::InlineOperator::Custom { ref name, ref args } => {
+                // The parser only accepts registered names, but the operator could have been
+                // built without it.
+                let factory = match registry::inline_factory(name) {
+                    Some(factory) => factory,
+                    None => {
+                        let e = TypeError::UnregisteredOperator { name: name.clone() };
+                        error_builder
+                            .report_error_for(&e)
+                            .with_code_pointer(&self, "")
+                            .send_report();
+                        return Err(e);
+                    }
+                };
+                let args: Vec<operator::Expr> = args.iter().cloned().map(Into::into).collect();
+                factory(&args).map_err(|e| {
+                    let e = TypeError::InvalidArguments {
+                        name: name.clone(),
+                        message: e.to_string(),
+                    };
+                    error_builder
+                        .report_error_for(&e)
+                        .with_code_pointer(&self, "")
+                        .send_report();
+                    e
+                })
+            }
         }
     }
 }
@@ -228,6 +338,103 @@ impl lang::Positioned<lang::AggregateFunction> {
 
                 Err(())
             }
+            lang::AggregateFunction::Custom { ref name, ref args } => {
+                let factory = match registry::aggregate_factory(name) {
+                    Some(factory) => factory,
+                    None => {
+                        error_builder
+                            .report_error_for(TypeError::UnregisteredOperator {
+                                name: name.clone(),
+                            })
+                            .with_code_pointer(&self, "")
+                            .send_report();
+                        return Err(());
+                    }
+                };
+                let args: Vec<operator::Expr> = args.iter().cloned().map(Into::into).collect();
+                factory(&args).map_err(|e| {
+                    error_builder
+                        .report_error_for(TypeError::InvalidArguments {
+                            name: name.clone(),
+                            message: e.to_string(),
+                        })
+                        .with_code_pointer(&self, "")
+                        .send_report();
+                })
+            }
+        }
+    }
+}
+
+impl lang::Positioned<lang::LogReduceOperator> {
+    /// After an aggregate there are no raw lines left, so the column to group has to be given.
+    pub fn semantic_analysis<T: ErrorBuilder>(
+        self,
+        in_agg: bool,
+        error_builder: &T,
+    ) -> Result<operator::LogReduce, TypeError> {
+        match self.value.input_column {
+            None if in_agg => {
+                let e = TypeError::LogReduceWithoutColumn;
+
+                error_builder
+                    .report_error_for(e.to_string())
+                    .with_code_pointer(&self, "No column given")
+                    .with_resolution("example: count by message | logreduce from message")
+                    .send_report();
+
+                Err(e)
+            }
+            input_column => Ok(operator::LogReduce::new(input_column.map(Into::into))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ErrorReporter, QueryContainer};
+    use crate::lang::{Positioned, QueryPosition};
+
+    struct Silent;
+
+    impl ErrorReporter for Silent {}
+
+    fn positioned<T>(value: T) -> Positioned<T> {
+        Positioned {
+            start_pos: QueryPosition(4),
+            end_pos: QueryPosition(16),
+            value,
+        }
+    }
+
+    #[test]
+    fn unregistered_operators() {
+        let container = QueryContainer::new("* | unregistered".to_string(), Box::new(Silent));
+        let inline = positioned(lang::InlineOperator::Custom {
+            name: "unregistered".to_string(),
+            args: Vec::new(),
+        });
+        match inline.semantic_analysis(&container) {
+            Err(TypeError::UnregisteredOperator { name }) => assert_eq!(name, "unregistered"),
+            _ => panic!("expected an error for an unregistered operator"),
         }
+        let aggregate = positioned(lang::AggregateFunction::Custom {
+            name: "unregistered".to_string(),
+            args: Vec::new(),
+        });
+        assert!(aggregate.semantic_analysis(&container).is_err());
+        let messages: Vec<_> = container
+            .errors()
+            .into_iter()
+            .map(|error| error.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "No operator named unregistered is registered",
+                "No operator named unregistered is registered"
+            ]
+        );
     }
 }
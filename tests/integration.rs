@@ -20,15 +20,265 @@ struct TestDefinition {
 #[cfg(test)]
 mod integration {
     use super::*;
-    use ag::pipeline::{ErrorReporter, Pipeline, QueryContainer};
+    use ag::data::{Aggregate, Record, Value};
+    use ag::lang::{InlineOperator, Operator};
+    use ag::operator::{
+        downcast, AggregateFunction, EvalError, EvaluatableBorrowed, Expr, UnaryPreAggFunction,
+    };
+    use ag::pipeline::{
+        parse_query, ErrorMode, ErrorReporter, OutputFormat, Pipeline, QueryContainer, QueryOutput,
+        RowSink,
+    };
     use assert_cli;
     use std::borrow::Borrow;
+    use std::collections::HashMap;
     use toml;
 
     pub struct EmptyErrorReporter;
 
     impl ErrorReporter for EmptyErrorReporter {}
 
+    #[derive(Default)]
+    struct CollectingSink {
+        records: Vec<Record>,
+        aggregates: Vec<(Aggregate, bool)>,
+    }
+
+    impl RowSink for CollectingSink {
+        fn record(&mut self, record: Record) -> Result<(), failure::Error> {
+            self.records.push(record);
+            Ok(())
+        }
+
+        fn aggregate(&mut self, aggregate: Aggregate, last: bool) -> Result<(), failure::Error> {
+            self.aggregates.push((aggregate, last));
+            Ok(())
+        }
+    }
+
+    /// Upper-cases a column.
+    #[derive(Clone)]
+    struct Shout {
+        column: Expr,
+        name: String,
+    }
+
+    impl UnaryPreAggFunction for Shout {
+        fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+            let value: &String = self.column.eval_borrowed(&rec.data)?;
+            let upper = value.to_uppercase();
+            Ok(Some(rec.put(&self.name, Value::Str(upper))))
+        }
+    }
+
+    /// The length of the longest value of a column.
+    struct Longest {
+        column: Expr,
+        longest: usize,
+    }
+
+    impl AggregateFunction for Longest {
+        fn process(&mut self, rec: &HashMap<String, Value>) -> Result<(), EvalError> {
+            let value: &String = self.column.eval_borrowed(rec)?;
+            self.longest = self.longest.max(value.len());
+            Ok(())
+        }
+
+        fn emit(&self) -> Value {
+            Value::Int(self.longest as i64)
+        }
+
+        fn empty_box(&self) -> Box<AggregateFunction> {
+            Box::new(Longest {
+                column: self.column.clone(),
+                longest: 0,
+            })
+        }
+
+        fn can_merge(&self) -> bool {
+            true
+        }
+
+        fn merge(&mut self, other: &AggregateFunction) {
+            self.longest = self.longest.max(downcast::<Longest>(other).longest);
+        }
+    }
+
+    /// Keeps the first value of a column.  It can't be merged, so it sees every record in order.
+    struct First {
+        column: Expr,
+        first: Option<Value>,
+    }
+
+    impl AggregateFunction for First {
+        fn process(&mut self, rec: &HashMap<String, Value>) -> Result<(), EvalError> {
+            if self.first.is_none() {
+                let value: &Value = self.column.eval_borrowed(rec)?;
+                self.first = Some(value.clone());
+            }
+            Ok(())
+        }
+
+        fn emit(&self) -> Value {
+            self.first.clone().unwrap_or(Value::None)
+        }
+
+        fn empty_box(&self) -> Box<AggregateFunction> {
+            Box::new(First {
+                column: self.column.clone(),
+                first: None,
+            })
+        }
+    }
+
+    fn single_column(args: &[Expr]) -> Result<Expr, failure::Error> {
+        match args {
+            [column] => Ok(column.clone()),
+            _ => Err(failure::err_msg("expected a single column")),
+        }
+    }
+
+    #[test]
+    fn registered_operators() {
+        ag::registry::register_inline("shout", |args| {
+            let column = single_column(args)?;
+            Ok(Box::new(Shout {
+                column,
+                name: "shouted".to_string(),
+            }))
+        })
+        .unwrap();
+        ag::registry::register_aggregate("longest", |args| {
+            Ok(Box::new(Longest {
+                column: single_column(args)?,
+                longest: 0,
+            }))
+        })
+        .unwrap();
+
+        let query = r#"* | json | shout(message) | longest(shouted) by level"#;
+        match Pipeline::compile(query)
+            .unwrap()
+            .run(
+                include_str!("../test_files/test_json.log")
+                    .lines()
+                    .filter(|line| line.contains("message")),
+            )
+            .unwrap()
+            .output
+        {
+            QueryOutput::Aggregate(aggregate) => {
+                assert_eq!(aggregate.columns, vec!["level", "_longest"]);
+                let longest: Vec<&Value> =
+                    aggregate.data.iter().map(|row| &row["_longest"]).collect();
+                assert_eq!(longest, vec![&Value::Int(20), &Value::Int(17)]);
+            }
+            QueryOutput::Records(_) => panic!("expected an aggregate"),
+        }
+
+        ag::registry::register_aggregate("first", |args| {
+            Ok(Box::new(First {
+                column: single_column(args)?,
+                first: None,
+            }))
+        })
+        .unwrap();
+        let lines: Vec<String> = (0..5000).map(|i| format!("{} {}", i % 3, i)).collect();
+        let query = r#"* | parse "* *" as k, i | first(i) by k | sort by k"#;
+        match Pipeline::compile(query).unwrap().run(lines).unwrap().output {
+            QueryOutput::Aggregate(aggregate) => {
+                let first: Vec<&Value> = aggregate.data.iter().map(|row| &row["_first"]).collect();
+                assert_eq!(first, vec![&Value::Int(0), &Value::Int(1), &Value::Int(2)]);
+            }
+            QueryOutput::Records(_) => panic!("expected an aggregate"),
+        }
+
+        // Names that start with a built-in operator.
+        ag::registry::register_inline("jsonify", |args| {
+            Ok(Box::new(Shout {
+                column: single_column(args)?,
+                name: "jsonified".to_string(),
+            }))
+        })
+        .unwrap();
+        ag::registry::register_aggregate("count_longest", |args| {
+            Ok(Box::new(Longest {
+                column: single_column(args)?,
+                longest: 0,
+            }))
+        })
+        .unwrap();
+        for query in &[
+            "* | json | jsonify(message) | count_longest(jsonified), count by level | sort by level",
+            "* | json | jsonify(message) | count, count_longest(jsonified) by level | sort by level",
+        ] {
+            match Pipeline::compile(query)
+                .unwrap()
+                .run(
+                    include_str!("../test_files/test_json.log")
+                        .lines()
+                        .filter(|line| line.contains("message")),
+                )
+                .unwrap()
+                .output
+            {
+                QueryOutput::Aggregate(aggregate) => {
+                    let longest: Vec<&Value> = aggregate
+                        .data
+                        .iter()
+                        .map(|row| &row["_count_longest"])
+                        .collect();
+                    assert_eq!(longest, vec![&Value::Int(20), &Value::Int(17)]);
+                }
+                QueryOutput::Records(_) => panic!("expected an aggregate"),
+            }
+        }
+
+        let errors = Pipeline::compile("* | shout").err().unwrap();
+        assert_eq!(
+            errors[0].message,
+            "Invalid arguments for shout: expected a single column"
+        );
+        let errors = Pipeline::compile("* | json | longst(message)")
+            .err()
+            .unwrap();
+        assert_eq!(
+            errors[0].resolutions,
+            vec![
+                "longst is not a valid operator",
+                "Did you mean \"longest\"?"
+            ]
+        );
+        let errors = Pipeline::compile("* | json | @errors | count")
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].message, "No macro named `errors`");
+        let errors = Pipeline::compile("* | json | split tags on ''")
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].message, "Split needs a non-empty separator");
+        let errors = Pipeline::compile("* | json | count by message | logreduce")
+            .err()
+            .unwrap();
+        assert_eq!(
+            errors[0].message,
+            "logreduce after an aggregate needs a column to group"
+        );
+        assert!(Pipeline::compile("* | json | count by message | logreduce from message").is_ok());
+    }
+
+    fn process_to_sink(query: &str) -> CollectingSink {
+        let query = QueryContainer::new(query.to_string(), Box::new(EmptyErrorReporter));
+        let pipeline = Pipeline::new(&query, OutputFormat::Table).unwrap();
+        let (sink, _) = pipeline
+            .process_to(
+                include_str!("../test_files/test_json.log").as_bytes(),
+                CollectingSink::default(),
+            )
+            .unwrap();
+        sink
+    }
+
     fn structured_test(s: &str) {
         let conf: TestDefinition = toml::from_str(s).unwrap();
         let out: &str = conf.output.borrow();
@@ -95,6 +345,11 @@ mod integration {
         structured_test(include_str!("structured_tests/sort_order.toml"));
     }
 
+    #[test]
+    fn chart() {
+        structured_test(include_str!("structured_tests/chart.toml"));
+    }
+
     #[test]
     fn total() {
         structured_test(include_str!("structured_tests/total.toml"));
@@ -129,7 +384,7 @@ mod integration {
             .fails()
             .and()
             .stderr()
-            .contains("[OPTIONS] <query|--self-update>")
+            .contains("[OPTIONS] <query|--query-file <query_file>|--self-update|--repl>")
             .unwrap();
     }
 
@@ -200,6 +455,571 @@ $None$       1")
             .unwrap();
     }
 
+    #[test]
+    fn file_before_query() {
+        // `-f` takes a single value, so the query can still come after it.
+        assert_cli::Assert::main_binary()
+            .with_args(&["-f", "test_files/test_json.log", "* | json | count"])
+            .stdout()
+            .is("_count\n--------------\n6")
+            .unwrap();
+    }
+
+    #[test]
+    fn file_column_output() {
+        let file_column = |query: &str, output: &str| {
+            assert_cli::Assert::main_binary().with_args(&[
+                query,
+                "-f",
+                "test_files/test_split.log",
+                "-o",
+                output,
+            ])
+        };
+        // The file is left out, unless the query asks for it.
+        file_column("* | json | fields except tags, hosts", "table")
+            .stdout()
+            .is("[id=1]\n[id=2]\n[id=3]")
+            .unwrap();
+        file_column("* | json | fields id, _file", "table")
+            .stdout()
+            .contains("[_file=test_files/test_split.log]        [id=1]")
+            .unwrap();
+        file_column(
+            "* | json | where _file != \"other.log\" | fields except tags, hosts",
+            "json",
+        )
+        .stdout()
+        .contains(r#"{"_file":"test_files/test_split.log","id":1}"#)
+        .unwrap();
+    }
+
+    #[test]
+    fn multiple_files() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | count by _file",
+                "--file",
+                "test_files/test_parse.log",
+                "-f",
+                "test_files/test_j*.log",
+                "-f",
+                "test_files/missing.log",
+            ])
+            .stdout()
+            .is("_file                            _count
+-----------------------------------------------
+test_files/test_parse.log        16
+test_files/test_json.log         6")
+            .stderr()
+            .contains("error: test_files/missing.log: No such file or directory")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&["* | count by _file", "--file", "test_files/test_parse.log"])
+            .stdout()
+            .is("_file                            _count
+-----------------------------------------------
+test_files/test_parse.log        16")
+            .stderr()
+            .is("")
+            .unwrap();
+        // `_file` is never set when reading stdin.
+        assert_cli::Assert::main_binary()
+            .stdin("a\nb\n")
+            .with_args(&["* | count by _file"])
+            .stderr()
+            .is("warning: Column `_file` wasn't in any of the first 2 records")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(assert_cli::Environment::inherit().insert("RUST_BACKTRACE", "0"))
+            .with_args(&["* | count", "--file", "test_files/missing.log"])
+            .fails()
+            .stdout()
+            .is("")
+            .stderr()
+            .contains("error: test_files/missing.log: No such file or directory")
+            .stderr()
+            .contains("None of the input files could be read")
+            .unwrap();
+    }
+
+    #[test]
+    fn recently_written_input() {
+        // A file that was just written is read as a stream rather than mapped.
+        let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("recently_written.log");
+        std::fs::write(&path, "a\nb\nc").unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&["* | count", "--file", path.to_str().unwrap()])
+            .stdout()
+            .is("_count\n--------------\n3")
+            .unwrap();
+    }
+
+    #[test]
+    fn compressed_input() {
+        for file_name in &[
+            "test_files/test_json_gz.log.gz",
+            "test_files/test_json_zst.log.zst",
+            "test_files/test_json_bz2.log.bz2",
+            "test_files/test_json_xz.log.xz",
+        ] {
+            assert_cli::Assert::main_binary()
+                .with_args(&["* | json | count by level", "--file", file_name])
+                .stdout()
+                .is("level        _count
+---------------------------
+info         3
+error        2
+$None$       1")
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn plain_input_like_bzip2() {
+        // Starts with the bzip2 magic, but isn't followed by a block size.
+        let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("bzh.log");
+        std::fs::write(&path, "BZh is not compressed\nnor is this").unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&["* | count", "--file", path.to_str().unwrap()])
+            .stdout()
+            .is("_count\n--------------\n2")
+            .unwrap();
+    }
+
+    #[test]
+    fn non_utf8_input() {
+        assert_cli::Assert::main_binary()
+            .with_args(&["closed", "--file", "test_files/non_utf8.log"])
+            .stdout()
+            .is("ERROR caf\u{fffd} closed \u{fffd}\u{fffd}")
+            .unwrap();
+    }
+
+    #[test]
+    fn error_modes() {
+        let env = assert_cli::Environment::inherit().insert("RUST_BACKTRACE", "0");
+        let query = "* | json | where num_things > 100";
+        let summary = "error summary:\n  where: 4 records missing key `num_things`";
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&[query, "--file", "test_files/test_json.log"])
+            .stdout()
+            .contains("[num_things=1102]")
+            .stderr()
+            .is(format!(
+                "{}{}",
+                "error: No value for key num_things\n".repeat(4),
+                summary
+            )
+            .as_str())
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&[
+                query,
+                "--file",
+                "test_files/test_json.log",
+                "--quiet-errors",
+            ])
+            .stdout()
+            .contains("[num_things=1102]")
+            .stderr()
+            .is(summary)
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&[query, "--file", "test_files/test_json.log", "--strict"])
+            .fails()
+            .stderr()
+            .is("Error: Stopped at the first error: where: No value for key num_things")
+            .unwrap();
+    }
+
+    #[test]
+    fn saved_queries_and_macros() {
+        let env = assert_cli::Environment::inherit()
+            .insert("RUST_BACKTRACE", "0")
+            .insert("XDG_CONFIG_HOME", "/nonexistent");
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .current_dir("test_files/project")
+            .with_args(&["@errors | count", "--file", "../test_json.log"])
+            .stdout()
+            .is("_count\n--------------\n2")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .current_dir("test_files/project")
+            .with_args(&["* | @levels", "--file", "../test_json.log"])
+            .stdout()
+            .contains("info")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .current_dir("test_files/project")
+            .with_args(&["* | @lvls", "--file", "../test_json.log"])
+            .fails()
+            .stderr()
+            .contains("No macro named `lvls`")
+            .stderr()
+            .contains("Did you mean `@levels`?")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .current_dir("test_files/project")
+            .with_args(&["* | @broken", "--file", "../test_json.log"])
+            .fails()
+            .stderr()
+            .contains("1 | * | @broken\n  |     ------- info: in `@broken`")
+            .stderr()
+            .contains("1 | json | where 5\n  |              ^ This is constant")
+            .unwrap();
+    }
+
+    #[test]
+    fn broken_config() {
+        let env = assert_cli::Environment::inherit()
+            .insert("RUST_BACKTRACE", "0")
+            .insert("XDG_CONFIG_HOME", "/nonexistent");
+        // The config is only read for queries that refer to it.
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .current_dir("test_files/broken_config")
+            .with_args(&["* | json | count", "--file", "../test_json.log"])
+            .stdout()
+            .is("_count\n--------------\n6")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .current_dir("test_files/broken_config")
+            .with_args(&[
+                r#"* | json | fillnull with "none@example.com" | count # @errors"#,
+                "--file",
+                "../test_json.log",
+            ])
+            .stdout()
+            .is("_count\n--------------\n6")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .current_dir("test_files/broken_config")
+            .with_args(&["* | @errors | count", "--file", "../test_json.log"])
+            .fails()
+            .stderr()
+            .contains("Invalid config file")
+            .unwrap();
+    }
+
+    #[test]
+    fn repl() {
+        let env = assert_cli::Environment::inherit()
+            .insert("RUST_BACKTRACE", "0")
+            .insert("XDG_DATA_HOME", "/nonexistent");
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&["--repl", "--file", "test_files/test_json.log"])
+            .stdin("* | json | count\n* | json | where 5\n\n* | json | count by level\n* | count by _file\n")
+            .stdout()
+            .contains("_count\n--------------\n6")
+            .stdout()
+            .contains("info         3")
+            .stdout()
+            .contains("test_files/test_json.log        6")
+            .stderr()
+            .contains("1 | * | json | where 5\n  |                  ^ This is constant")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&["--repl"])
+            .fails()
+            .stderr()
+            .contains("the input has to come from --file")
+            .unwrap();
+    }
+
+    #[test]
+    fn rename_fillnull() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | fillnull with 0 num_things | sum(num_things) by level \
+                 | rename _sum as things | sort by level",
+                "--file",
+                "test_files/test_json.log",
+                "--output",
+                "json",
+            ])
+            .stdout()
+            .is(r#"{"level":null,"things":0}
+{"level":"error","things":0}
+{"level":"info","things":1114}"#)
+            .unwrap();
+    }
+
+    #[test]
+    fn split() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | split tags as tag | count by tag | sort by tag",
+                "--file",
+                "test_files/test_split.log",
+                "--output",
+                "json",
+            ])
+            .stdout()
+            .is(r#"{"tag":"a","_count":1}
+{"tag":"b","_count":2}
+{"tag":"c","_count":1}"#)
+            .unwrap();
+    }
+
+    #[test]
+    fn dedup() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | dedup level keep=last",
+                "--file",
+                "test_files/test_json.log",
+                "--output",
+                "json",
+            ])
+            .stdout()
+            .is(r#"{"level":"error","message":"So many more errors!"}
+{"event_duration":1002.5,"level":"info","message":"A different event"}
+{"level":null}"#)
+            .unwrap();
+    }
+
+    #[test]
+    fn logreduce() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | logreduce | limit 1",
+                "--file",
+                "test_files/test_parse.log",
+                "--output",
+                "json",
+            ])
+            .stdout()
+            .is(r#"{"_template":"INFO Server db-<num> loaded response in <num>ms","_count":14,"_example":"INFO Server db-1 loaded response in 500ms"}"#)
+            .unwrap();
+    }
+
+    #[test]
+    fn query_file() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "--query-file",
+                "test_files/errors_by_message.agq",
+                "--file",
+                "test_files/test_json.log",
+                "--output",
+                "json",
+            ])
+            .stdout()
+            .contains(r#"{"message":"So many more errors!","_count":1}"#)
+            .stdout()
+            .contains(r#"{"message":"Oh now an error!","_count":1}"#)
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(assert_cli::Environment::inherit().insert("RUST_BACKTRACE", "0"))
+            .with_args(&[
+                "--query-file",
+                "test_files/bad_query.agq",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .fails()
+            .stderr()
+            .contains("--> test_files/bad_query.agq:3:11")
+            .stderr()
+            .contains("3 |   | where 5   # not a condition\n  |           ^ This is constant")
+            .unwrap();
+    }
+
+    #[test]
+    fn fmt() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "--fmt",
+                "* |json|avg( x ) by y | where a == $min | sort by _average dsc",
+            ])
+            .stdout()
+            .is("* | json | average(x) by y | where a == $min | sort by _average desc")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&["--fmt", "--query-file", "test_files/errors_by_message.agq"])
+            .stdout()
+            .is("error\n  | json\n  | where level == \"error\"\n  | count by message")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&["--fmt", "@errors |json|@by-host  # per host"])
+            .stdout()
+            .is("@errors | json | @by-host")
+            .unwrap();
+        let env = assert_cli::Environment::inherit().insert("RUST_BACKTRACE", "1");
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&["--fmt", "* | json | 5"])
+            .fails()
+            .stderr()
+            .contains("5 is not a valid operator")
+            .stderr()
+            .doesnt_contain("backtrace")
+            .unwrap();
+    }
+
+    #[test]
+    fn query_params() {
+        let env = assert_cli::Environment::inherit().insert("RUST_BACKTRACE", "0");
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&[
+                "* | json | where level == $level | count",
+                "--param",
+                "level=error",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .stdout()
+            .is("_count\n--------------\n2")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&[
+                "* | json | where num_things > $min",
+                "--param",
+                "min=lots",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .fails()
+            .stderr()
+            .contains("Parameter $min is compared as a number, but its value `lots` isn't a number")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_env(&env)
+            .with_args(&[
+                "* | json | where level == $lvl",
+                "--param",
+                "level=error",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .fails()
+            .stderr()
+            .contains("No value for parameter $lvl")
+            .stderr()
+            .contains("Parameter $level isn't used in the query")
+            .unwrap();
+    }
+
+    #[test]
+    fn explain() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "--explain",
+                r#"Error "no such*" | parse "* *" as a, b | count by a | total(_count) | limit"#,
+            ])
+            .stdout()
+            .contains("Filters:\n  (?m)(?i)Error  (from Error)\n  (?m)(?i)no such\\*  (from \"no such*\")\n")
+            .stdout()
+            .contains("  parse \"* *\" as a, b\n    out: a, b\n")
+            .stdout()
+            .contains(
+                r#"Aggregates, on one thread after the workers aggregate their part of the input:
+  count by a
+    in: a
+    out: a, _count
+  total(_count)  (applied to each row of the aggregate)
+    in: _count
+    out: a, _count, _total
+  limit  (default count of 10)
+    out: a, _count, _total"#,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn unknown_columns() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | count by levl",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .stderr()
+            .is("warning: Column `levl` wasn't in any of the first 6 records (did you mean `level`?)")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | count by level | sort by _cont",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .stderr()
+            .contains("warning: Column `_cont` isn't produced by any earlier operator")
+            .stderr()
+            .contains("Did you mean `_count`?")
+            .unwrap();
+    }
+
+    #[test]
+    fn output_formats() {
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | count by level",
+                "--file",
+                "test_files/test_json.log",
+                "--output",
+                "json",
+            ])
+            .stdout()
+            .is(r#"{"level":"info","_count":3}
+{"level":"error","_count":2}
+{"level":null,"_count":1}"#)
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | fields level, num_things",
+                "--file",
+                "test_files/test_json.log",
+                "-o",
+                "csv",
+            ])
+            .stdout()
+            .is("level,num_things
+info,1102
+error,
+error,
+info,12
+info,
+,")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&[
+                "* | json | count by level",
+                "--file",
+                "test_files/test_json.log",
+                "-o",
+                "markdown",
+            ])
+            .stdout()
+            .is("| level | _count |
+| --- | --- |
+| info | 3 |
+| error | 2 |
+| $None$ | 1 |")
+            .unwrap();
+        assert_cli::Assert::main_binary()
+            .with_args(&["*", "-o", "yaml"])
+            .fails()
+            .and()
+            .stderr()
+            .contains("Unknown output format `yaml`")
+            .unwrap();
+    }
+
     #[test]
     fn aggregate_of_aggregate() {
         assert_cli::Assert::main_binary()
@@ -291,9 +1111,144 @@ $None$       1")
             .unwrap();
     }
 
+    #[test]
+    fn process_to_sink_records() {
+        let sink = process_to_sink(r#"* | json | where level == "error""#);
+        let messages: Vec<&Value> = sink
+            .records
+            .iter()
+            .map(|rec| &rec.data["message"])
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                &Value::Str("Oh now an error!".to_string()),
+                &Value::Str("So many more errors!".to_string())
+            ]
+        );
+        assert!(sink.aggregates.is_empty());
+    }
+
+    #[test]
+    fn process_to_sink_aggregate() {
+        let sink = process_to_sink("* | json | count by level");
+        assert!(sink.records.is_empty());
+        let (aggregate, last) = sink.aggregates.last().unwrap();
+        assert!(last);
+        assert_eq!(aggregate.columns, vec!["level", "_count"]);
+        let counts: Vec<&Value> = aggregate.data.iter().map(|row| &row["_count"]).collect();
+        assert_eq!(counts, vec![&Value::Int(3), &Value::Int(2), &Value::Int(1)]);
+    }
+
+    #[test]
+    fn library_parse_query() {
+        let query = parse_query("* | json | limit 5").unwrap();
+        assert_eq!(query.operators.len(), 2);
+        match &query.operators[1] {
+            Operator::Inline(op) => match &op.value {
+                InlineOperator::Limit { count: Some(count) } => assert_eq!(count.value, 5.0),
+                other => panic!("expected a limit, got {:?}", other),
+            },
+            _ => panic!("expected an inline operator"),
+        }
+
+        let errors = parse_query("* | json | cont").err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Expected an operator");
+        assert_eq!(errors[0].annotations[0].0, 11..15);
+        assert_eq!(
+            errors[0].resolutions,
+            vec!["cont is not a valid operator", "Did you mean \"count\"?"]
+        );
+    }
+
+    #[test]
+    fn library_run_records() {
+        let pipeline = Pipeline::compile(r#"* | parse "* *" as a, b | fields a"#).unwrap();
+        let result = pipeline.run(vec!["x 1", "unparsed", "y 2"]).unwrap();
+        match result.output {
+            QueryOutput::Records(records) => {
+                let values: Vec<&Value> = records.iter().map(|rec| &rec.data["a"]).collect();
+                assert_eq!(
+                    values,
+                    vec![&Value::Str("x".to_string()), &Value::Str("y".to_string())]
+                );
+            }
+            QueryOutput::Aggregate(_) => panic!("expected records"),
+        }
+        assert_eq!(result.diagnostics.errors.total(), 0);
+
+        let pipeline = Pipeline::compile("* | json | where n > 1").unwrap();
+        let result = pipeline.run(vec![r#"{"n": 2}"#, "{}"]).unwrap();
+        assert_eq!(
+            result.diagnostics.errors.summary(),
+            vec!["where: 1 record missing key `n`"]
+        );
+    }
+
+    #[test]
+    fn library_process_to_diagnostics() {
+        let input = "{\"level\": \"info\", \"n\": 2}\n{\"level\": \"warn\"}\n";
+        let query = "* | json | where n > 1 | count by levl";
+        let (sink, diagnostics) = Pipeline::compile(query)
+            .unwrap()
+            .process_to(input.as_bytes(), CollectingSink::default())
+            .unwrap();
+        assert_eq!(sink.aggregates.len(), 1);
+        assert_eq!(
+            diagnostics.warnings,
+            vec!["Column `levl` wasn't in the first record (did you mean `level`?)"]
+        );
+        assert_eq!(diagnostics.errors.total(), 1);
+        assert_eq!(
+            diagnostics.errors.summary(),
+            vec!["where: 1 record missing key `n`"]
+        );
+
+        let err = Pipeline::compile(query)
+            .unwrap()
+            .error_mode(ErrorMode::Strict)
+            .process_to(input.as_bytes(), CollectingSink::default())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Stopped at the first error: where: No value for key n"
+        );
+    }
+
+    #[test]
+    fn library_post_aggregate_errors() {
+        let lines = vec![
+            r#"{"level": "info", "n": 1}"#,
+            r#"{"level": "info", "n": 2}"#,
+            r#"{"level": "error", "n": 3}"#,
+        ];
+        let errors = |query: &str| {
+            let pipeline = Pipeline::compile(query).unwrap();
+            pipeline
+                .run(lines.clone())
+                .unwrap()
+                .diagnostics
+                .errors
+                .summary()
+        };
+        assert_eq!(
+            errors("* | json | count by level | where _count > limit"),
+            vec!["where: 2 records missing key `limit`"]
+        );
+        assert_eq!(
+            errors("* | json | count by level | sum(n) by level | sum(m)"),
+            vec![
+                "sum: 2 records missing key `m`",
+                "sum: 2 records missing key `n`"
+            ]
+        );
+    }
+
     fn ensure_parses(query: &str) {
         let query_container = QueryContainer::new(query.to_string(), Box::new(EmptyErrorReporter));
-        Pipeline::new(&query_container).expect(&format!(
+        Pipeline::new(&query_container, OutputFormat::Table).expect(&format!(
             "Query: `{}` from the README should have parsed",
             query
         ));